@@ -0,0 +1,391 @@
+use crate::config::{AudioConfig, AudioOutput, Config, SoundCue};
+use crate::overlay::Timer;
+use anyhow::{Context, Result};
+use chrono::Local;
+use rodio::buffer::SamplesBuffer;
+use rodio::{Decoder, OutputStream, Sink, Source};
+use std::f32::consts::TAU;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Write};
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+
+const TONE_SAMPLE_RATE: u32 = 44_100;
+// Fade the built-in tones in and out over this long so they don't click
+const TONE_FADE_SECS: f32 = 0.005;
+
+/// Something on a timer that can play a sound
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cue {
+    Start,
+    Warning,
+    Expiry,
+    Tick,
+}
+
+impl Cue {
+    pub const ALL: [Cue; 4] = [Cue::Start, Cue::Warning, Cue::Expiry, Cue::Tick];
+
+    /// Get the name of the cue's table in the audio config
+    pub fn key(self) -> &'static str {
+        match self {
+            Cue::Start => "start",
+            Cue::Warning => "warning",
+            Cue::Expiry => "expiry",
+            Cue::Tick => "tick",
+        }
+    }
+
+    pub fn sound_cue(self, audio: &AudioConfig) -> &SoundCue {
+        match self {
+            Cue::Start => &audio.start,
+            Cue::Warning => &audio.warning,
+            Cue::Expiry => &audio.expiry,
+            Cue::Tick => &audio.tick,
+        }
+    }
+
+    pub fn sound_cue_mut(self, audio: &mut AudioConfig) -> &mut SoundCue {
+        match self {
+            Cue::Start => &mut audio.start,
+            Cue::Warning => &mut audio.warning,
+            Cue::Expiry => &mut audio.expiry,
+            Cue::Tick => &mut audio.tick,
+        }
+    }
+
+    /// Get the built-in sound for the cue
+    fn tone(self) -> Sound {
+        match self {
+            Cue::Start => Sound::tone(660.0, 0.12, 1),
+            Cue::Warning => Sound::tone(880.0, 0.15, 2),
+            Cue::Expiry => Sound::tone(440.0, 0.2, 3),
+            Cue::Tick => Sound::tone(1200.0, 0.03, 1),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Sound {
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// Interleaved samples from -1.0 to 1.0
+    pub samples: Vec<f32>,
+}
+
+impl Sound {
+    /// Synthesize `beeps` sine beeps of `secs` each, with a gap as long as a beep between
+    fn tone(frequency: f32, secs: f32, beeps: usize) -> Self {
+        let beep_len = (secs * TONE_SAMPLE_RATE as f32) as usize;
+        let fade_len = (TONE_FADE_SECS * TONE_SAMPLE_RATE as f32) as usize;
+
+        let mut samples = Vec::with_capacity(beep_len * beeps * 2);
+        for beep in 0..beeps {
+            if beep > 0 {
+                samples.resize(samples.len() + beep_len, 0.0);
+            }
+            samples.extend((0..beep_len).map(|i| {
+                let fade = (i.min(beep_len - i) as f32 / fade_len as f32).min(1.0);
+                (TAU * frequency * i as f32 / TONE_SAMPLE_RATE as f32).sin() * fade
+            }));
+        }
+
+        Self {
+            channels: 1,
+            sample_rate: TONE_SAMPLE_RATE,
+            samples,
+        }
+    }
+
+    fn load_wav(path: &str) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open sound: {path:?}"))?;
+        let decoder = Decoder::new(BufReader::new(file))
+            .with_context(|| format!("Failed to decode sound: {path:?}"))?;
+
+        Ok(Self {
+            channels: decoder.channels(),
+            sample_rate: decoder.sample_rate(),
+            samples: decoder.convert_samples().collect(),
+        })
+    }
+
+    fn duration(&self) -> Duration {
+        let frames = self.samples.len() / usize::from(self.channels.max(1));
+        Duration::from_secs_f64(frames as f64 / f64::from(self.sample_rate.max(1)))
+    }
+}
+
+/// Somewhere to send sounds
+pub trait AudioSink {
+    fn play(&mut self, cue: Cue, sound: &Sound, volume: f32);
+}
+
+/// Drops every sound
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn play(&mut self, _cue: Cue, _sound: &Sound, _volume: f32) {}
+}
+
+/// Logs a line per sound to a file instead of playing it
+pub struct FileSink {
+    file: File,
+}
+
+impl FileSink {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audio log: {path:?}"))?;
+        Ok(Self { file })
+    }
+}
+
+impl AudioSink for FileSink {
+    fn play(&mut self, cue: Cue, sound: &Sound, volume: f32) {
+        let line = format!(
+            "{} {} volume={volume:.2} duration={:.3}s\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            cue.key(),
+            sound.duration().as_secs_f32(),
+        );
+        if let Err(e) = self.file.write_all(line.as_bytes()) {
+            eprintln!("Failed to write audio log: {e:?}");
+        }
+    }
+}
+
+/// Plays sounds on the default audio device. The output stream can't leave the thread
+/// it was opened on, so it lives on its own thread that's fed sounds over a channel
+pub struct DeviceSink {
+    tx: Sender<(Sound, f32)>,
+}
+
+impl DeviceSink {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel::<(Sound, f32)>();
+
+        std::thread::spawn(move || {
+            let (_stream, handle) = match OutputStream::try_default() {
+                Ok(output) => output,
+                Err(e) => {
+                    eprintln!("Failed to open audio device: {e:?}");
+                    return;
+                }
+            };
+
+            // Runs until the sink is dropped
+            for (sound, volume) in rx {
+                match Sink::try_new(&handle) {
+                    Ok(sink) => {
+                        sink.set_volume(volume);
+                        sink.append(SamplesBuffer::new(
+                            sound.channels,
+                            sound.sample_rate,
+                            sound.samples,
+                        ));
+                        sink.detach();
+                    }
+                    Err(e) => eprintln!("Failed to play sound: {e:?}"),
+                }
+            }
+        });
+
+        Self { tx }
+    }
+}
+
+impl AudioSink for DeviceSink {
+    fn play(&mut self, _cue: Cue, sound: &Sound, volume: f32) {
+        let _ = self.tx.send((sound.clone(), volume));
+    }
+}
+
+/// Plays the configured sounds as timers start, reach warning stages and expire
+pub struct AudioCues {
+    audio: AudioConfig,
+    sink: Box<dyn AudioSink>,
+    sounds: Vec<(Cue, Sound)>,
+}
+
+impl AudioCues {
+    pub fn new(audio: &AudioConfig) -> Self {
+        let sink: Box<dyn AudioSink> = match audio.output {
+            _ if !audio.enabled => Box::new(NullSink),
+            AudioOutput::Device => Box::new(DeviceSink::new()),
+            AudioOutput::Null => Box::new(NullSink),
+            AudioOutput::File => match FileSink::open(&audio.output_file) {
+                Ok(sink) => Box::new(sink),
+                Err(e) => {
+                    eprintln!("{e:?}");
+                    Box::new(NullSink)
+                }
+            },
+        };
+
+        // Fall back to the built-in tone for sounds that can't be loaded
+        let sounds = Cue::ALL
+            .into_iter()
+            .map(|cue| {
+                let path = &cue.sound_cue(audio).sound_path;
+                let sound = if path.is_empty() {
+                    cue.tone()
+                } else {
+                    Sound::load_wav(path).unwrap_or_else(|e| {
+                        eprintln!("{e:?}");
+                        cue.tone()
+                    })
+                };
+                (cue, sound)
+            })
+            .collect();
+
+        Self {
+            audio: audio.clone(),
+            sink,
+            sounds,
+        }
+    }
+
+    pub fn play(&mut self, cue: Cue) {
+        let volume = cue.sound_cue(&self.audio).volume;
+        if !self.audio.enabled || volume <= 0.0 {
+            return;
+        }
+
+        if let Some((_, sound)) = self.sounds.iter().find(|(c, _)| *c == cue) {
+            self.sink.play(cue, sound, volume.min(1.0));
+        }
+    }
+
+    /// Play the cues for every point in the countdown that `timers` passed in the last
+    /// `elapsed`. Call this before expired timers are removed, so their expiry is heard
+    pub fn on_frame(&mut self, config: &Config, timers: &[Timer], elapsed: Duration) {
+        let tick_ms = (1..=self.audio.tick_seconds as i64).map(|secs| secs * 1000);
+
+        let mut cues = Vec::new();
+        for timer in timers {
+            let passed = |ms: i64| timer.passed(ms, elapsed);
+            let cue = if passed(0) {
+                Cue::Expiry
+            } else if config
+                .warning_stages
+                .iter()
+                .any(|stage| passed((stage.threshold * 1000.0) as i64))
+            {
+                Cue::Warning
+            } else if tick_ms.clone().any(passed) {
+                Cue::Tick
+            } else {
+                continue;
+            };
+
+            // Timers passing the same point together are heard once
+            if !cues.contains(&cue) {
+                cues.push(cue);
+            }
+        }
+
+        for cue in cues {
+            self.play(cue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    /// Set up cues that log to a fresh file named after the test, with the tick audible
+    fn logged_cues(name: &str, enabled: bool) -> (AudioCues, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "{}-{name}-{}.log",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let audio = AudioConfig {
+            enabled,
+            output: AudioOutput::File,
+            output_file: path.to_string_lossy().into_owned(),
+            tick: SoundCue {
+                volume: 0.3,
+                sound_path: "".to_string(),
+            },
+            ..AudioConfig::default()
+        };
+        (AudioCues::new(&audio), path)
+    }
+
+    /// Get the names of the cues logged to `path`, in order
+    fn logged(path: &PathBuf) -> Vec<String> {
+        let contents = fs::read_to_string(path).unwrap_or_default();
+        let _ = fs::remove_file(path);
+        contents
+            .lines()
+            .map(|line| line.split_whitespace().nth(2).unwrap().to_string())
+            .collect()
+    }
+
+    fn frame(name: &str, remaining_ms: &[i64]) -> Vec<String> {
+        let (mut cues, path) = logged_cues(name, true);
+        let timers: Vec<Timer> = remaining_ms
+            .iter()
+            .map(|&remaining| Timer::with_remaining(10_000, remaining, 0))
+            .collect();
+        cues.on_frame(&Config::default(), &timers, FRAME);
+        drop(cues);
+        logged(&path)
+    }
+
+    #[test]
+    fn cue_for_each_point_passed() {
+        assert_eq!(frame("expiry", &[-20]), ["expiry"]);
+        assert_eq!(frame("warning", &[4_950]), ["warning"]);
+        assert_eq!(frame("tick", &[2_950]), ["tick"]);
+        assert!(frame("between", &[3_500]).is_empty());
+        assert!(frame("lingering", &[-1_000]).is_empty());
+    }
+
+    #[test]
+    fn each_cue_plays_once_per_frame() {
+        assert_eq!(frame("together", &[-20, -50]), ["expiry"]);
+        assert_eq!(
+            frame("mixed", &[2_950, -20, 4_950]),
+            ["tick", "expiry", "warning"]
+        );
+    }
+
+    #[test]
+    fn silent_cues_are_skipped() {
+        let (mut cues, path) = logged_cues("silent", true);
+        cues.audio.tick.volume = 0.0;
+        cues.on_frame(
+            &Config::default(),
+            &[Timer::with_remaining(10_000, 1_950, 0)],
+            FRAME,
+        );
+        cues.play(Cue::Start);
+        drop(cues);
+        assert_eq!(logged(&path), ["start"]);
+    }
+
+    #[test]
+    fn disabled_cues_use_null_sink() {
+        let (mut cues, path) = logged_cues("disabled", false);
+        cues.play(Cue::Start);
+        cues.on_frame(
+            &Config::default(),
+            &[Timer::with_remaining(10_000, -20, 0)],
+            FRAME,
+        );
+        assert!(!path.exists());
+    }
+}
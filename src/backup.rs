@@ -0,0 +1,253 @@
+use crate::config::{Config, flatten_table, read_table};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BACKUP_DIR_NAME: &str = "backups";
+const BACKUP_PREFIX: &str = "config-";
+const BACKUP_EXTENSION: &str = ".toml";
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+/// A previous version of config.toml
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub created: DateTime<Local>,
+}
+
+/// A single value that differs between the current config file and a backup
+#[derive(Debug, Clone)]
+pub struct BackupChange {
+    pub key: String,
+    pub current: Option<toml::Value>,
+    pub backup: Option<toml::Value>,
+}
+
+impl Display for BackupChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |value: &Option<toml::Value>| match value {
+            Some(value) => value.to_string(),
+            None => "(unset)".to_string(),
+        };
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.key,
+            describe(&self.current),
+            describe(&self.backup)
+        )
+    }
+}
+
+/// Get the directory that backups of the config file at `config_path` are stored in
+fn backup_dir(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(BACKUP_DIR_NAME)
+}
+
+/// Copy the config file at `config_path` into the backup directory, then delete the
+/// oldest backups so that at most `max_backups` are kept
+pub fn create(config_path: &Path, max_backups: usize) -> Result<()> {
+    let backup_dir = backup_dir(config_path);
+
+    if max_backups > 0 && config_path.exists() {
+        fs::create_dir_all(&backup_dir)
+            .with_context(|| format!("Failed to create backup directory: {backup_dir:?}"))?;
+
+        let timestamp = Local::now().format(BACKUP_TIMESTAMP_FORMAT);
+        let backup_path = backup_dir.join(format!("{BACKUP_PREFIX}{timestamp}{BACKUP_EXTENSION}"));
+        fs::copy(config_path, &backup_path)
+            .with_context(|| format!("Failed to back up config file to {backup_path:?}"))?;
+    }
+
+    // Also runs when backups are turned off, so lowering the limit clears out the old ones
+    for backup in list_in(&backup_dir)?.into_iter().skip(max_backups) {
+        fs::remove_file(&backup.path)
+            .with_context(|| format!("Failed to remove old backup: {:?}", backup.path))?;
+    }
+
+    Ok(())
+}
+
+/// List all backups, newest first
+pub fn list() -> Result<Vec<Backup>> {
+    list_in(&backup_dir(&Config::get_default_config_path()?))
+}
+
+fn list_in(backup_dir: &Path) -> Result<Vec<Backup>> {
+    if !backup_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let entries = fs::read_dir(backup_dir)
+        .with_context(|| format!("Failed to read backup directory: {backup_dir:?}"))?;
+
+    let mut backups: Vec<Backup> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let timestamp = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix(BACKUP_PREFIX)?
+                .strip_suffix(BACKUP_EXTENSION)?;
+            let naive = NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT).ok()?;
+            let created = Local.from_local_datetime(&naive).earliest()?;
+            Some(Backup { path, created })
+        })
+        .collect();
+
+    backups.sort_by(|a, b| b.path.cmp(&a.path));
+
+    Ok(backups)
+}
+
+/// Compare a backup against the current config file
+pub fn diff(backup: &Backup) -> Result<Vec<BackupChange>> {
+    diff_against(&Config::get_default_config_path()?, backup)
+}
+
+fn diff_against(config_path: &Path, backup: &Backup) -> Result<Vec<BackupChange>> {
+    let current = flatten_table(&read_table(config_path)?.unwrap_or_default());
+    let previous = flatten_table(&read_table(&backup.path)?.unwrap_or_default());
+
+    let mut keys: Vec<&String> = current.keys().chain(previous.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    Ok(keys
+        .into_iter()
+        .filter(|key| current.get(*key) != previous.get(*key))
+        .map(|key| BackupChange {
+            key: key.clone(),
+            current: current.get(key).cloned(),
+            backup: previous.get(key).cloned(),
+        })
+        .collect())
+}
+
+/// Replace the config file with a backup. The current file is backed up first so the
+/// restore can be undone
+pub fn restore(backup: &Backup, max_backups: usize) -> Result<()> {
+    restore_to(&Config::get_default_config_path()?, backup, max_backups)
+}
+
+fn restore_to(config_path: &Path, backup: &Backup, max_backups: usize) -> Result<()> {
+    // Read the backup before rotating, since rotating may delete it
+    let contents = fs::read_to_string(&backup.path)
+        .with_context(|| format!("Failed to read backup: {:?}", backup.path))?;
+
+    create(config_path, max_backups)?;
+
+    fs::write(config_path, contents)
+        .with_context(|| format!("Failed to write config file: {config_path:?}"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    /// Make a fresh directory named after the test and get the config path inside it
+    fn config_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "{}-backup-{name}-{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("config.toml")
+    }
+
+    /// Write `contents` to the config file and back it up
+    fn save(path: &Path, contents: &str, max_backups: usize) {
+        fs::write(path, contents).unwrap();
+        create(path, max_backups).unwrap();
+        // Backups are named by the millisecond they were made in
+        sleep(Duration::from_millis(5));
+    }
+
+    fn contents(backups: &[Backup]) -> Vec<String> {
+        backups
+            .iter()
+            .map(|backup| fs::read_to_string(&backup.path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn oldest_backups_are_rotated_out() {
+        let path = config_path("rotate");
+        for i in 1..=4 {
+            save(&path, &format!("max_timers = {i}"), 2);
+        }
+
+        let backups = list_in(&backup_dir(&path)).unwrap();
+        assert_eq!(contents(&backups), ["max_timers = 4", "max_timers = 3"]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn turning_backups_off_removes_them() {
+        let path = config_path("off");
+        save(&path, "max_timers = 1", 5);
+        save(&path, "max_timers = 2", 5);
+        assert_eq!(list_in(&backup_dir(&path)).unwrap().len(), 2);
+
+        create(&path, 0).unwrap();
+        assert!(list_in(&backup_dir(&path)).unwrap().is_empty());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn diff_lists_changed_values() {
+        let path = config_path("diff");
+        save(
+            &path,
+            "max_timers = 3\ntimer_start = 5.0\n[theme]\nfont_path = \"a\"",
+            5,
+        );
+        fs::write(
+            &path,
+            "max_timers = 3\ntimer_start = 9.0\n[theme]\nfont_path = \"b\"\nextra = 1",
+        )
+        .unwrap();
+
+        let backup = &list_in(&backup_dir(&path)).unwrap()[0];
+        let changes: Vec<String> = diff_against(&path, backup)
+            .unwrap()
+            .iter()
+            .map(BackupChange::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "theme.extra: 1 -> (unset)",
+                "theme.font_path: \"b\" -> \"a\"",
+                "timer_start: 9.0 -> 5.0",
+            ]
+        );
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn restore_backs_up_the_current_file() {
+        let path = config_path("restore");
+        save(&path, "max_timers = 1", 5);
+        fs::write(&path, "max_timers = 2").unwrap();
+
+        let backup = list_in(&backup_dir(&path)).unwrap().remove(0);
+        restore_to(&path, &backup, 5).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "max_timers = 1");
+        let backups = list_in(&backup_dir(&path)).unwrap();
+        assert_eq!(contents(&backups), ["max_timers = 2", "max_timers = 1"]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,147 +1,1229 @@
-use anyhow::{Context, Result};
+use crate::backup;
+use anyhow::{Context, Result, anyhow, bail};
 use directories::BaseDirs;
+use egui::Color32;
 use rdev::{Button, Key};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml_edit::{DocumentMut, Item, Table, Value};
 
 const PROGRAM_DIR_NAME: &str = env!("CARGO_PKG_NAME");
 const PROGRAM_CONFIG_NAME: &str = "config.toml";
+const PROGRAM_TEAM_CONFIG_NAME: &str = "team.toml";
+const PORTABLE_MARKER_NAMES: [&str; 2] = ["portable", "portable.txt"];
+const ENV_PREFIX: &str = "VAL_SMOKE_TIMER_";
+const PROFILE_KEY: &str = "profile";
+const PROFILES_KEY: &str = "profiles";
+/// Placeholders that `subtext_template` fills in for each timer
+pub const SUBTEXT_PLACEHOLDERS: [(&str, &str); 6] = [
+    ("{label}", "the subtext label, if shown"),
+    ("{n}", "the timer's number, if shown"),
+    ("{elapsed}", "seconds since the timer started"),
+    ("{ends_at}", "the time of day the timer runs out"),
+    ("{profile}", "the selected profile"),
+    (
+        "{charges_left}",
+        "how many more timers fit before the overlay is full",
+    ),
+];
+// Replaced by `warning_stages`, still read from older config files
+const LEGACY_RED_TEXT_KEY: &str = "enable_red_text";
+const LEGACY_RED_TEXT_THRESHOLD_KEY: &str = "red_text_threshold";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Name of the display to put the overlay on, or empty for the primary display
+    pub monitor_name: String,
+    /// Index of the display among all detected displays, used to tell apart displays
+    /// that share a name
+    pub monitor_index: usize,
+    pub anchor: Anchor,
+    /// Offset of the overlay from its anchor point
     pub initial_pos: (f32, f32),
-    pub start_key: String,
-    pub cancelable_keys: Vec<String>,
-    pub confirm_key: String,
+    pub position_unit: PositionUnit,
+    pub start_key: InputBinding,
+    pub cancelable_keys: Vec<InputBinding>,
+    pub confirm_key: InputBinding,
+    /// Key that toggles dragging the overlay into place while the timers are running
+    pub reposition_key: InputBinding,
+    pub enable_reposition_key: bool,
     pub timer_start: f32,
+    pub time_format: TimeFormat,
+    pub time_rounding: TimeRounding,
     pub max_timers: usize,
     pub subtext_string: String,
+    /// Subtext drawn with each timer, see `SUBTEXT_PLACEHOLDERS` for what it can contain
+    pub subtext_template: String,
+    pub subtext_position: SubtextPosition,
     pub show_subtext: bool,
     pub show_numbering: bool,
+    /// PNG shown next to each timer, e.g. the ability icon, or empty for none
+    pub icon_path: String,
     pub add_new_on_left: bool,
     pub overwrite_oldest: bool,
-    pub enable_red_text: bool,
-    pub red_text_threshold: f32,
+    /// Keep each timer in the slot and number it started in, filling the first free slot
+    pub stable_slots: bool,
+    /// Seconds an expired timer stays on screen as a fading card, or 0 to remove it at once
+    pub expired_linger: f32,
+    /// Shown on expired timers instead of the zeroed time, e.g. "DOWN", or empty
+    pub expired_text: String,
+    pub layout: OverlayLayout,
+    pub grid_columns: usize,
+    pub timer_spacing: f32,
+    pub timer_style: TimerStyle,
+    /// Points in the countdown where the timer text changes color
+    pub warning_stages: Vec<WarningStage>,
+    /// Fade between the colors of consecutive stages instead of switching at each one
+    pub blend_warning_stages: bool,
+    /// Pulse the timer once it reaches the most urgent warning stage
+    pub pulse_final_stage: bool,
+    pub theme: Theme,
+    pub audio: AudioConfig,
+    pub hooks: HookConfig,
+    /// Keep a log of every timer event in the session in the log directory
+    pub session_log: SessionLogFormat,
+    pub max_backups: usize,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            monitor_name: "".to_string(),
+            monitor_index: 0,
+            anchor: Anchor::TopLeft,
             initial_pos: (0.0, 0.0),
-            start_key: "Key:KeyE".to_string(),
+            position_unit: PositionUnit::Pixels,
+            start_key: InputBinding::Key(Key::KeyE),
             cancelable_keys: vec![
-                "Key:KeyE".to_string(),
-                "Key:KeyQ".to_string(),
-                "Key:KeyC".to_string(),
-                "Key:KeyX".to_string(),
-                "Key:Escape".to_string(),
-                "Key:Num1".to_string(),
-                "Key:Num2".to_string(),
-                "Key:Num3".to_string(),
-                "Key:Num4".to_string(),
+                InputBinding::Key(Key::KeyE),
+                InputBinding::Key(Key::KeyQ),
+                InputBinding::Key(Key::KeyC),
+                InputBinding::Key(Key::KeyX),
+                InputBinding::Key(Key::Escape),
+                InputBinding::Key(Key::Num1),
+                InputBinding::Key(Key::Num2),
+                InputBinding::Key(Key::Num3),
+                InputBinding::Key(Key::Num4),
             ],
-            confirm_key: "Mouse:Right".to_string(),
+            confirm_key: InputBinding::Mouse(Button::Right),
+            reposition_key: InputBinding::Key(Key::F8),
+            enable_reposition_key: false,
             timer_start: 19.25,
+            time_format: TimeFormat::Hundredths,
+            time_rounding: TimeRounding::Down,
             max_timers: 3,
             subtext_string: "".to_string(),
+            subtext_template: "{label} {n}".to_string(),
+            subtext_position: SubtextPosition::Below,
             show_subtext: true,
             show_numbering: true,
+            icon_path: "".to_string(),
             add_new_on_left: true,
             overwrite_oldest: false,
-            enable_red_text: true,
-            red_text_threshold: 5.0,
+            stable_slots: false,
+            expired_linger: 0.0,
+            expired_text: "".to_string(),
+            layout: OverlayLayout::Row,
+            grid_columns: 2,
+            timer_spacing: 30.0,
+            timer_style: TimerStyle::Text,
+            warning_stages: vec![WarningStage {
+                threshold: 5.0,
+                color: Color32::RED,
+            }],
+            blend_warning_stages: false,
+            pulse_final_stage: false,
+            theme: Theme::default(),
+            audio: AudioConfig::default(),
+            hooks: HookConfig::default(),
+            session_log: SessionLogFormat::Jsonl,
+            max_backups: 10,
         }
     }
 }
 
 impl Config {
+    /// Get the directory that config, logs and backups are stored in. In portable mode
+    /// this is the directory of the executable, otherwise it's the local config directory
+    pub fn get_config_dir() -> Result<PathBuf> {
+        if let Some(portable_dir) = Self::get_portable_dir() {
+            return Ok(portable_dir);
+        }
+
+        let base_dirs = BaseDirs::new().with_context(|| "Failed to get base dirs")?;
+
+        Ok(base_dirs.config_local_dir().join(PROGRAM_DIR_NAME))
+    }
+
+    /// Get the executable's directory if a portable marker or a config file sits beside it
+    pub fn get_portable_dir() -> Option<PathBuf> {
+        let exe_path = std::env::current_exe().ok()?;
+        let exe_dir = exe_path.parent()?;
+
+        let is_portable = PORTABLE_MARKER_NAMES
+            .iter()
+            .chain([&PROGRAM_CONFIG_NAME])
+            .any(|name| exe_dir.join(name).is_file());
+
+        is_portable.then(|| exe_dir.to_path_buf())
+    }
+
     /// Get the default config file path
     pub fn get_default_config_path() -> Result<PathBuf> {
-        let base_dirs = BaseDirs::new().with_context(|| "Failed to get base dirs")?;
+        Ok(Self::get_config_dir()?.join(PROGRAM_CONFIG_NAME))
+    }
 
-        Ok(base_dirs
-            .config_local_dir()
-            .join(PROGRAM_DIR_NAME)
-            .join(PROGRAM_CONFIG_NAME))
+    /// Get the path of the shared team config file
+    pub fn get_team_config_path() -> Result<PathBuf> {
+        Ok(Self::get_config_dir()?.join(PROGRAM_TEAM_CONFIG_NAME))
     }
 
-    /// Load config from file, or return default if it doesn't exist or fails to parse
+    /// Serialize config into a TOML table. This goes through TOML text because
+    /// serializing straight into a table widens floats, turning 19.3 into 19.299999237060547
+    pub fn to_table(&self) -> Result<toml::Table> {
+        let contents = toml::to_string(self).with_context(|| "Failed to serialize config")?;
+        toml::from_str(&contents).with_context(|| "Failed to parse serialized config")
+    }
+}
+
+/// The point on the screen that the overlay is positioned relative to. Timers grow away
+/// from it so the overlay stays on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    CenterLeft,
+    Center,
+    CenterRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl Anchor {
+    pub const ALL: [Anchor; 9] = [
+        Anchor::TopLeft,
+        Anchor::TopCenter,
+        Anchor::TopRight,
+        Anchor::CenterLeft,
+        Anchor::Center,
+        Anchor::CenterRight,
+        Anchor::BottomLeft,
+        Anchor::BottomCenter,
+        Anchor::BottomRight,
+    ];
+
+    /// Get how far across the screen the anchor sits on each axis, from 0.0 to 1.0
+    pub fn factor(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::TopCenter => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::CenterLeft => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::CenterRight => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::BottomCenter => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+impl Display for Anchor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Anchor::TopLeft => write!(f, "Top Left"),
+            Anchor::TopCenter => write!(f, "Top Center"),
+            Anchor::TopRight => write!(f, "Top Right"),
+            Anchor::CenterLeft => write!(f, "Center Left"),
+            Anchor::Center => write!(f, "Center"),
+            Anchor::CenterRight => write!(f, "Center Right"),
+            Anchor::BottomLeft => write!(f, "Bottom Left"),
+            Anchor::BottomCenter => write!(f, "Bottom Center"),
+            Anchor::BottomRight => write!(f, "Bottom Right"),
+        }
+    }
+}
+
+/// How the overlay's offset from its anchor is measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionUnit {
+    /// Points on the selected display, which are scaled by its scale factor
+    Pixels,
+    /// Percentage of the selected display's size
+    Percent,
+}
+
+/// How the overlay arranges its timers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayLayout {
+    Row,
+    Column,
+    Grid,
+}
+
+impl OverlayLayout {
+    pub const ALL: [OverlayLayout; 3] = [
+        OverlayLayout::Row,
+        OverlayLayout::Column,
+        OverlayLayout::Grid,
+    ];
+}
+
+impl Display for OverlayLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverlayLayout::Row => write!(f, "Horizontal Row"),
+            OverlayLayout::Column => write!(f, "Vertical Stack"),
+            OverlayLayout::Grid => write!(f, "Grid"),
+        }
+    }
+}
+
+/// How the remaining time is written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    /// `SS.cc`
+    Hundredths,
+    /// `SS.c`
+    Tenths,
+    /// `SS`
+    Seconds,
+    /// `M:SS`
+    MinutesSeconds,
+    /// `M:SS` from a minute up, `SS.cc` below
+    Auto,
+}
+
+impl TimeFormat {
+    pub const ALL: [TimeFormat; 5] = [
+        TimeFormat::Hundredths,
+        TimeFormat::Tenths,
+        TimeFormat::Seconds,
+        TimeFormat::MinutesSeconds,
+        TimeFormat::Auto,
+    ];
+
+    /// Get the precision in milliseconds that `remaining_ms` is shown with when it's
+    /// rounded the `rounding` way
+    pub fn unit_ms(self, remaining_ms: i64, rounding: TimeRounding) -> i64 {
+        match self {
+            TimeFormat::Hundredths => 10,
+            TimeFormat::Tenths => 100,
+            TimeFormat::Seconds | TimeFormat::MinutesSeconds => 1000,
+            // Decided on the rounded time, so a full minute always shows as `1:00`
+            TimeFormat::Auto if rounding.round(remaining_ms, 1000) >= 60_000 => 1000,
+            TimeFormat::Auto => 10,
+        }
+    }
+
+    /// Format `remaining_ms`, which must already be rounded to `unit_ms`
+    pub fn format(self, remaining_ms: i64) -> String {
+        let seconds = remaining_ms / 1000;
+        match self {
+            TimeFormat::Hundredths => format!("{seconds:02}.{:02}", remaining_ms % 1000 / 10),
+            TimeFormat::Tenths => format!("{seconds:02}.{}", remaining_ms % 1000 / 100),
+            TimeFormat::Seconds => format!("{seconds:02}"),
+            TimeFormat::MinutesSeconds => format!("{}:{:02}", seconds / 60, seconds % 60),
+            TimeFormat::Auto if remaining_ms >= 60_000 => {
+                TimeFormat::MinutesSeconds.format(remaining_ms)
+            }
+            TimeFormat::Auto => TimeFormat::Hundredths.format(remaining_ms),
+        }
+    }
+}
+
+impl Display for TimeFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeFormat::Hundredths => write!(f, "SS.cc"),
+            TimeFormat::Tenths => write!(f, "SS.c"),
+            TimeFormat::Seconds => write!(f, "Whole Seconds"),
+            TimeFormat::MinutesSeconds => write!(f, "M:SS"),
+            TimeFormat::Auto => write!(f, "Auto (M:SS above a minute)"),
+        }
+    }
+}
+
+/// Which way the remaining time is rounded to the precision of the time format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeRounding {
+    Down,
+    Nearest,
+    /// Only shows zero once the timer has actually run out
+    Up,
+}
+
+impl TimeRounding {
+    pub const ALL: [TimeRounding; 3] =
+        [TimeRounding::Down, TimeRounding::Nearest, TimeRounding::Up];
+
+    /// Round `ms` to a multiple of `unit_ms`
+    pub fn round(self, ms: i64, unit_ms: i64) -> i64 {
+        let units = match self {
+            TimeRounding::Down => ms / unit_ms,
+            TimeRounding::Nearest => (ms + unit_ms / 2) / unit_ms,
+            TimeRounding::Up => (ms + unit_ms - 1) / unit_ms,
+        };
+        units * unit_ms
+    }
+}
+
+impl Display for TimeRounding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeRounding::Down => write!(f, "Round Down"),
+            TimeRounding::Nearest => write!(f, "Round to Nearest"),
+            TimeRounding::Up => write!(f, "Round Up"),
+        }
+    }
+}
+
+/// Where the subtext goes relative to its timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubtextPosition {
+    Above,
+    Below,
+    Beside,
+}
+
+impl SubtextPosition {
+    pub const ALL: [SubtextPosition; 3] = [
+        SubtextPosition::Above,
+        SubtextPosition::Below,
+        SubtextPosition::Beside,
+    ];
+}
+
+impl Display for SubtextPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubtextPosition::Above => write!(f, "Above"),
+            SubtextPosition::Below => write!(f, "Below"),
+            SubtextPosition::Beside => write!(f, "Beside"),
+        }
+    }
+}
+
+/// How each timer shows its remaining time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimerStyle {
+    Text,
+    /// A shrinking bar under the text
+    Bar,
+    /// A shrinking ring around the text
+    Ring,
+}
+
+impl TimerStyle {
+    pub const ALL: [TimerStyle; 3] = [TimerStyle::Text, TimerStyle::Bar, TimerStyle::Ring];
+}
+
+impl Display for TimerStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimerStyle::Text => write!(f, "Text Only"),
+            TimerStyle::Bar => write!(f, "Progress Bar"),
+            TimerStyle::Ring => write!(f, "Progress Ring"),
+        }
+    }
+}
+
+/// Colors and sizes the overlay is drawn with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Theme {
+    #[serde(with = "hex_color")]
+    pub text_color: Color32,
+    #[serde(with = "hex_color")]
+    pub subtext_color: Color32,
+    #[serde(with = "hex_color")]
+    pub background_color: Color32,
+    /// Opacity of the timer backgrounds, from 0 (invisible) to 255 (solid)
+    pub background_opacity: u8,
+    pub timer_font_size: f32,
+    pub subtext_font_size: f32,
+    pub corner_radius: f32,
+    /// TTF/OTF font for the timers and subtext, or empty for the built-in monospace font
+    pub font_path: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            text_color: Color32::WHITE,
+            subtext_color: Color32::WHITE,
+            background_color: Color32::BLACK,
+            background_opacity: 180,
+            timer_font_size: 48.0,
+            subtext_font_size: 12.0,
+            corner_radius: 5.0,
+            font_path: "".to_string(),
+        }
+    }
+}
+
+impl Theme {
+    /// Get the background color with the background opacity applied
+    pub fn background(&self) -> Color32 {
+        let [r, g, b, _] = self.background_color.to_srgba_unmultiplied();
+        Color32::from_rgba_unmultiplied(r, g, b, self.background_opacity)
+    }
+}
+
+/// Sounds played as timers start, reach warning stages and expire
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioConfig {
+    pub enabled: bool,
+    pub output: AudioOutput,
+    /// File that the `file` output logs each sound to
+    pub output_file: String,
+    pub start: SoundCue,
+    /// Played each time a timer reaches one of the warning stages
+    pub warning: SoundCue,
+    pub expiry: SoundCue,
+    /// Played every second over the last `tick_seconds` of each timer
+    pub tick: SoundCue,
+    pub tick_seconds: f32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            output: AudioOutput::Device,
+            output_file: "".to_string(),
+            start: SoundCue {
+                volume: 0.5,
+                sound_path: "".to_string(),
+            },
+            warning: SoundCue {
+                volume: 0.5,
+                sound_path: "".to_string(),
+            },
+            expiry: SoundCue {
+                volume: 0.8,
+                sound_path: "".to_string(),
+            },
+            tick: SoundCue {
+                volume: 0.0,
+                sound_path: "".to_string(),
+            },
+            tick_seconds: 3.0,
+        }
+    }
+}
+
+/// A sound and how loud it plays
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SoundCue {
+    /// From 0.0 (off) to 1.0
+    pub volume: f32,
+    /// WAV file to play, or empty for the built-in tone
+    pub sound_path: String,
+}
+
+/// Where sounds are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioOutput {
+    /// The default audio device
+    Device,
+    /// Nowhere, for muting without losing the other settings
+    Null,
+    /// A line per sound appended to `output_file`, for checking cues without speakers
+    File,
+}
+
+impl AudioOutput {
+    pub const ALL: [AudioOutput; 3] = [AudioOutput::Device, AudioOutput::Null, AudioOutput::File];
+}
+
+impl Display for AudioOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioOutput::Device => write!(f, "Speakers"),
+            AudioOutput::Null => write!(f, "None"),
+            AudioOutput::File => write!(f, "Log File"),
+        }
+    }
+}
+
+/// Commands run on timer events, each one empty to run nothing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HookConfig {
+    pub on_start: String,
+    pub on_expire: String,
+    /// Run when a running timer is replaced to make room for a new one
+    pub on_cancel: String,
+    /// Seconds a command may run before it's killed
+    pub timeout: f32,
+}
+
+impl Default for HookConfig {
+    fn default() -> Self {
+        Self {
+            on_start: "".to_string(),
+            on_expire: "".to_string(),
+            on_cancel: "".to_string(),
+            timeout: 5.0,
+        }
+    }
+}
+
+/// The file format of the session log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionLogFormat {
+    Off,
+    /// A JSON object per line
+    Jsonl,
+    Csv,
+}
+
+impl SessionLogFormat {
+    pub const ALL: [SessionLogFormat; 3] = [
+        SessionLogFormat::Off,
+        SessionLogFormat::Jsonl,
+        SessionLogFormat::Csv,
+    ];
+}
+
+impl Display for SessionLogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionLogFormat::Off => write!(f, "Off"),
+            SessionLogFormat::Jsonl => write!(f, "JSON Lines"),
+            SessionLogFormat::Csv => write!(f, "CSV"),
+        }
+    }
+}
+
+/// A point in the countdown where the timer text changes color
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WarningStage {
+    /// Seconds remaining when the stage starts
+    pub threshold: f32,
+    #[serde(with = "hex_color")]
+    pub color: Color32,
+}
+
+/// Serialize colors as `#RRGGBB` hex strings, or `#RRGGBBAA` if they're translucent
+mod hex_color {
+    use anyhow::{Result, bail};
+    use egui::Color32;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color32, serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b, a] = color.to_srgba_unmultiplied();
+        if a == u8::MAX {
+            serializer.collect_str(&format_args!("#{r:02X}{g:02X}{b:02X}"))
+        } else {
+            serializer.collect_str(&format_args!("#{r:02X}{g:02X}{b:02X}{a:02X}"))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color32, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        parse(&s).map_err(serde::de::Error::custom)
+    }
+
+    fn parse(s: &str) -> Result<Color32> {
+        let hex = s.trim().trim_start_matches('#');
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("Invalid color '{s}', expected '#RRGGBB' or '#RRGGBBAA'");
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+        let alpha = if hex.len() == 8 { channel(6)? } else { u8::MAX };
+        Ok(Color32::from_rgba_unmultiplied(
+            channel(0)?,
+            channel(2)?,
+            channel(4)?,
+            alpha,
+        ))
+    }
+}
+
+/// A layer that config values are resolved from, in order of increasing priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigLayer {
+    Default,
+    Team,
+    User,
+    Profile,
+    Env,
+}
+
+impl Display for ConfigLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigLayer::Default => write!(f, "default"),
+            ConfigLayer::Team => write!(f, "{PROGRAM_TEAM_CONFIG_NAME}"),
+            ConfigLayer::User => write!(f, "{PROGRAM_CONFIG_NAME}"),
+            ConfigLayer::Profile => write!(f, "profile"),
+            ConfigLayer::Env => write!(f, "environment"),
+        }
+    }
+}
+
+/// The effective config along with the layers each of its values came from
+#[derive(Debug, Clone)]
+pub struct ResolvedConfig {
+    pub config: Config,
+    /// The profile applied on top of the user file, if any
+    pub profile: Option<String>,
+    /// Every profile defined in the team and user files
+    pub profiles: Vec<String>,
+    /// Why loading failed, in which case `config` holds the defaults
+    pub error: Option<String>,
+    resolved: toml::Table,
+    sources: BTreeMap<String, ConfigLayer>,
+}
+
+impl ResolvedConfig {
+    /// Resolve config from all layers, or return default if it fails. The files are left
+    /// alone on failure so that they can be fixed without losing anything
     pub fn load() -> Self {
-        match Self::try_load() {
-            Ok(config) => config,
+        match Self::resolve(None) {
+            Ok(resolved) => resolved,
             Err(e) => {
-                eprintln!("Failed to load config: {e:?}. Using and saving defaults");
-                let default_config = Self::default();
-                // Save the default config
-                if let Err(save_err) = default_config.save() {
-                    eprintln!("Failed to save default config: {save_err:?}");
+                eprintln!("Failed to load config: {e:?}. Using defaults");
+                Self {
+                    error: Some(format!("{e:#}")),
+                    ..Self::from_config(Config::default())
+                }
+            }
+        }
+    }
+
+    /// Resolve config again with a different profile selected
+    pub fn with_profile(&self, profile: Option<String>) -> Result<Self> {
+        Self::resolve(Some(profile))
+    }
+
+    fn from_config(config: Config) -> Self {
+        let resolved = config.to_table().unwrap_or_default();
+        let mut sources = BTreeMap::new();
+        for (key, value) in &resolved {
+            record_sources(&mut sources, key, value, ConfigLayer::Default);
+        }
+
+        Self {
+            config,
+            profile: None,
+            profiles: Vec::new(),
+            error: None,
+            resolved,
+            sources,
+        }
+    }
+
+    /// Layer the team file, user file, profile and environment on top of the defaults.
+    /// `profile_override` replaces the profile selected by the user file or environment
+    fn resolve(profile_override: Option<Option<String>>) -> Result<Self> {
+        let mut resolved = Self::from_config(Config::default());
+        let mut profiles = toml::Table::new();
+        let mut selected = None;
+
+        let layers = [
+            (ConfigLayer::Team, Config::get_team_config_path()?),
+            (ConfigLayer::User, Config::get_default_config_path()?),
+        ];
+        for (layer, path) in layers {
+            let Some(mut table) = read_table(&path)? else {
+                continue;
+            };
+
+            if let Some(value) = table.remove(PROFILES_KEY) {
+                let toml::Value::Table(layer_profiles) = value else {
+                    bail!("`{PROFILES_KEY}` in {path:?} must be a table");
+                };
+                // A profile defined in both files keeps the team's values that the user
+                // file doesn't set
+                merge_tables(&mut profiles, layer_profiles);
+            }
+            if let Some(value) = table.remove(PROFILE_KEY) {
+                let name = value
+                    .as_str()
+                    .with_context(|| format!("`{PROFILE_KEY}` in {path:?} must be a string"))?;
+                selected = (!name.is_empty()).then(|| name.to_string());
+            }
+
+            resolved.apply(layer, &table);
+        }
+
+        let mut env = env_table(std::env::vars(), &resolved.resolved);
+        if let Some(value) = env.remove(PROFILE_KEY) {
+            selected = value.as_str().map(str::to_string);
+        }
+
+        let selected =
+            profile_override
+                .unwrap_or(selected)
+                .filter(|name| match profiles.get(name) {
+                    Some(_) => true,
+                    None => {
+                        eprintln!("Profile {name:?} isn't defined in any config file, ignoring it");
+                        false
+                    }
+                });
+        if let Some(name) = &selected {
+            let Some(toml::Value::Table(profile)) = profiles.get(name) else {
+                bail!("Profile {name:?} must be a table");
+            };
+            resolved.apply(ConfigLayer::Profile, profile);
+        }
+
+        resolved.apply(ConfigLayer::Env, &env);
+
+        resolved.config = resolved
+            .resolved
+            .clone()
+            .try_into()
+            .with_context(|| "Failed to parse config file")?;
+        resolved.profile = selected;
+        resolved.profiles = profiles.keys().cloned().collect();
+
+        Ok(resolved)
+    }
+
+    fn apply(&mut self, layer: ConfigLayer, table: &toml::Table) {
+        let mut table = table.clone();
+        migrate_legacy_keys(&mut table);
+        apply_layer(&mut self.resolved, &table, layer, &mut self.sources, "");
+    }
+
+    /// Get the layer that the value at the dotted `path` was resolved from, or `None` if
+    /// it has changed since. `current` is the edited config from `Config::to_table`
+    pub fn source(&self, current: &toml::Table, path: &str) -> Option<ConfigLayer> {
+        if lookup(current, path) != lookup(&self.resolved, path) {
+            return None;
+        }
+
+        Some(
+            self.sources
+                .get(path)
+                .copied()
+                .unwrap_or(ConfigLayer::Default),
+        )
+    }
+
+    /// Save `config` to the user file. Only values that were changed or already came from
+    /// the user file are written, so the other layers keep applying on the next load.
+    /// Changes to values set by the selected profile are written to that profile
+    pub fn save(&self, config: &Config) -> Result<()> {
+        self.save_to(&Config::get_default_config_path()?, config)
+    }
+
+    fn save_to(&self, path: &Path, config: &Config) -> Result<()> {
+        let current = config.to_table()?;
+        let mut profile_values = toml::Table::new();
+        let mut table = self.user_values(&current, "", &mut profile_values);
+
+        // Nothing was read from a file that failed to load, so its profile and legacy keys
+        // are kept for when it's fixed
+        if self.error.is_some() {
+            return save_user_table(path, &table, &[], config.max_backups);
+        }
+
+        match &self.profile {
+            Some(profile) => {
+                table.insert(PROFILE_KEY.to_string(), profile.clone().into());
+                if !profile_values.is_empty() {
+                    let mut profiles = toml::Table::new();
+                    profiles.insert(profile.clone(), profile_values.into());
+                    table.insert(PROFILES_KEY.to_string(), profiles.into());
                 }
-                default_config
+                save_user_table(
+                    path,
+                    &table,
+                    &[LEGACY_RED_TEXT_KEY, LEGACY_RED_TEXT_THRESHOLD_KEY],
+                    config.max_backups,
+                )
             }
+            None => save_user_table(
+                path,
+                &table,
+                &[
+                    PROFILE_KEY,
+                    LEGACY_RED_TEXT_KEY,
+                    LEGACY_RED_TEXT_THRESHOLD_KEY,
+                ],
+                config.max_backups,
+            ),
         }
     }
 
-    fn try_load() -> Result<Self> {
-        let path = Self::get_default_config_path()?;
+    /// Collect the values of `current` to write to the user file. Changed values that came
+    /// from the profile are collected into `profile` instead, since the profile would
+    /// override them at the top level
+    fn user_values(
+        &self,
+        current: &toml::Table,
+        prefix: &str,
+        profile: &mut toml::Table,
+    ) -> toml::Table {
+        let mut table = toml::Table::new();
+        for (key, value) in current {
+            let path = join_path(prefix, key);
+            let changed = lookup(&self.resolved, &path) != Some(value);
+            match (value, self.sources.get(&path)) {
+                (toml::Value::Table(nested), _) => {
+                    let nested = self.user_values(nested, &path, profile);
+                    if !nested.is_empty() {
+                        table.insert(key.clone(), nested.into());
+                    }
+                }
+                (_, Some(ConfigLayer::Profile)) if changed => {
+                    let segments: Vec<&str> = path.split('.').collect();
+                    insert_path(profile, &segments, value.clone());
+                }
+                (_, Some(ConfigLayer::Profile)) => {}
+                (_, Some(ConfigLayer::User)) => {
+                    table.insert(key.clone(), value.clone());
+                }
+                _ if changed => {
+                    table.insert(key.clone(), value.clone());
+                }
+                _ => {}
+            }
+        }
+        table
+    }
+}
+
+/// Read a config layer from disk, or `None` if the file doesn't exist
+pub(crate) fn read_table(path: &Path) -> Result<Option<toml::Table>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {path:?}"))?;
 
-        if !path.exists() {
-            return Ok(Self::default());
+    let table = toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse config file: {path:?}"))?;
+
+    Ok(Some(table))
+}
+
+/// Collect `VAL_SMOKE_TIMER_*` environment variables into a config layer. A double
+/// underscore reaches into nested tables. Values are taken as the type of the value they
+/// override in `resolved`, or as TOML for other keys, falling back to plain strings
+fn env_table(vars: impl Iterator<Item = (String, String)>, resolved: &toml::Table) -> toml::Table {
+    let mut table = toml::Table::new();
+
+    for (name, raw) in vars {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let key = key.to_lowercase();
+        let segments: Vec<&str> = key.split("__").collect();
+
+        let value = match lookup(resolved, &segments.join(".")) {
+            // Text that happens to look like a number or bool is still text
+            Some(toml::Value::String(_)) => toml::Value::String(raw),
+            _ if key == PROFILE_KEY => toml::Value::String(raw),
+            existing => {
+                let parsed = toml::from_str::<toml::Table>(&format!("value = {raw}"))
+                    .ok()
+                    .and_then(|mut parsed| parsed.remove("value"));
+                match (existing, parsed) {
+                    (Some(toml::Value::Float(_)), Some(toml::Value::Integer(i))) => {
+                        toml::Value::Float(i as f64)
+                    }
+                    (_, Some(parsed)) => parsed,
+                    (_, None) => toml::Value::String(raw),
+                }
+            }
+        };
+
+        insert_path(&mut table, &segments, value);
+    }
+
+    table
+}
+
+fn insert_path(table: &mut toml::Table, segments: &[&str], value: toml::Value) {
+    match segments {
+        [] => {}
+        [last] => {
+            table.insert(last.to_string(), value);
         }
+        [first, rest @ ..] => {
+            let entry = table
+                .entry(first.to_string())
+                .or_insert_with(|| toml::Table::new().into());
+            if let toml::Value::Table(nested) = entry {
+                insert_path(nested, rest, value);
+            }
+        }
+    }
+}
 
-        let contents = fs::read_to_string(&path)
-            .with_context(|| format!("Failed to read config file: {path:?}"))?;
+/// Merge `layer` into `table`, recursing into the tables that both of them have
+fn merge_tables(table: &mut toml::Table, layer: toml::Table) {
+    for (key, value) in layer {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(nested)) => {
+                merge_tables(existing, nested);
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Turn the single red text warning of older config files into a warning stage, unless
+/// the layer already sets its own stages
+fn migrate_legacy_keys(table: &mut toml::Table) {
+    let enabled = table.remove(LEGACY_RED_TEXT_KEY);
+    let threshold = table.remove(LEGACY_RED_TEXT_THRESHOLD_KEY);
+    if (enabled.is_none() && threshold.is_none()) || table.contains_key("warning_stages") {
+        return;
+    }
+
+    let mut stages = toml::value::Array::new();
+    if enabled.and_then(|v| v.as_bool()).unwrap_or(true) {
+        let threshold = threshold
+            .and_then(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64)))
+            .unwrap_or(5.0);
+        let mut stage = toml::Table::new();
+        stage.insert("threshold".to_string(), threshold.into());
+        stage.insert("color".to_string(), "#FF0000".into());
+        stages.push(stage.into());
+    }
+    table.insert("warning_stages".to_string(), stages.into());
+}
+
+/// Overlay `layer` onto `table`, merging nested tables and recording `source` for every
+/// value it sets
+fn apply_layer(
+    table: &mut toml::Table,
+    layer: &toml::Table,
+    source: ConfigLayer,
+    sources: &mut BTreeMap<String, ConfigLayer>,
+    prefix: &str,
+) {
+    for (key, value) in layer {
+        let path = join_path(prefix, key);
+        match (table.get_mut(key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(nested)) => {
+                apply_layer(existing, nested, source, sources, &path);
+            }
+            _ => {
+                table.insert(key.clone(), value.clone());
+                record_sources(sources, &path, value, source);
+            }
+        }
+    }
+}
+
+fn record_sources(
+    sources: &mut BTreeMap<String, ConfigLayer>,
+    path: &str,
+    value: &toml::Value,
+    source: ConfigLayer,
+) {
+    match value {
+        toml::Value::Table(nested) => {
+            for (key, value) in nested {
+                record_sources(sources, &join_path(path, key), value, source);
+            }
+        }
+        _ => {
+            sources.insert(path.to_string(), source);
+        }
+    }
+}
+
+/// Flatten nested tables into a map from dotted paths to values
+pub fn flatten_table(table: &toml::Table) -> BTreeMap<String, toml::Value> {
+    fn flatten_into(flat: &mut BTreeMap<String, toml::Value>, table: &toml::Table, prefix: &str) {
+        for (key, value) in table {
+            let path = join_path(prefix, key);
+            match value {
+                toml::Value::Table(nested) => flatten_into(flat, nested, &path),
+                _ => {
+                    flat.insert(path, value.clone());
+                }
+            }
+        }
+    }
+
+    let mut flat = BTreeMap::new();
+    flatten_into(&mut flat, table, "");
+    flat
+}
+
+fn lookup<'a>(table: &'a toml::Table, path: &str) -> Option<&'a toml::Value> {
+    let (first, rest) = match path.split_once('.') {
+        Some((first, rest)) => (first, Some(rest)),
+        None => (path, None),
+    };
 
-        let config: Config =
-            toml::from_str(&contents).with_context(|| "Failed to parse config file")?;
+    match (table.get(first)?, rest) {
+        (toml::Value::Table(nested), Some(rest)) => lookup(nested, rest),
+        (value, None) => Some(value),
+        _ => None,
+    }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
 
-        Ok(config)
+/// Write `table` to the user config file at `path`, removing any top-level `removed` keys. The
+/// previous version of the file is kept as a backup if anything changed
+fn save_user_table(
+    path: &Path,
+    table: &toml::Table,
+    removed: &[&str],
+    max_backups: usize,
+) -> Result<()> {
+    // Create parent directory if it doesn't exist
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create config directory: {parent:?}"))?;
     }
 
-    /// Save config to file
-    pub fn save(&self) -> Result<()> {
-        let path = Self::get_default_config_path()?;
+    let updated = toml::to_string_pretty(table).with_context(|| "Failed to serialize config")?;
 
-        // Create parent directory if it doesn't exist
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create config directory: {parent:?}"))?;
+    // Update the existing file in place so that comments, ordering and keys we don't
+    // know about survive the save. If it's missing or unparseable, write it fresh
+    let existing = fs::read_to_string(path).ok();
+    let contents = match existing
+        .as_deref()
+        .and_then(|existing| existing.parse::<DocumentMut>().ok())
+    {
+        Some(mut document) => {
+            let updated = updated
+                .parse::<DocumentMut>()
+                .with_context(|| "Failed to parse serialized config")?;
+            merge_table(document.as_table_mut(), updated.as_table());
+            for key in removed {
+                document.remove(key);
+            }
+            document.to_string()
         }
+        None => updated,
+    };
+
+    if existing.is_some_and(|existing| existing != contents)
+        && let Err(e) = backup::create(path, max_backups)
+    {
+        eprintln!("Failed to back up config file: {e:?}");
+    }
 
-        let contents =
-            toml::to_string_pretty(self).with_context(|| "Failed to serialize config")?;
+    fs::write(path, contents).with_context(|| format!("Failed to write config file: {path:?}"))?;
 
-        fs::write(&path, contents)
-            .with_context(|| format!("Failed to write config file: {path:?}"))?;
+    Ok(())
+}
 
-        Ok(())
+/// Copy the values of `updated` into `existing`. Values that didn't change are left
+/// untouched, changed values keep their surrounding comments and whitespace, and keys
+/// that only exist in `existing` are kept as they are
+fn merge_table(existing: &mut Table, updated: &Table) {
+    for (key, updated_item) in updated.iter() {
+        match (existing.get_mut(key), updated_item) {
+            (Some(Item::Table(existing_table)), Item::Table(updated_table)) => {
+                merge_table(existing_table, updated_table);
+            }
+            (Some(Item::Value(existing_value)), Item::Value(updated_value)) => {
+                if !values_equal(existing_value, updated_value) {
+                    let decor = existing_value.decor().clone();
+                    *existing_value = updated_value.clone();
+                    *existing_value.decor_mut() = decor;
+                }
+            }
+            (Some(existing_item), _) => *existing_item = updated_item.clone(),
+            (None, _) => {
+                existing.insert(key, updated_item.clone());
+            }
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Compare two TOML values while ignoring how they're formatted
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.value() == b.value(),
+        (Value::Integer(a), Value::Integer(b)) => a.value() == b.value(),
+        (Value::Float(a), Value::Float(b)) => a.value() == b.value(),
+        (Value::Boolean(a), Value::Boolean(b)) => a.value() == b.value(),
+        (Value::Datetime(a), Value::Datetime(b)) => a.value() == b.value(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| values_equal(a, b))
+        }
+        (Value::InlineTable(a), Value::InlineTable(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| values_equal(a, b)))
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InputBinding {
     Key(Key),
     Mouse(Button),
 }
 
-impl InputBinding {
-    pub fn from_string(s: &str) -> Option<Self> {
+impl FromStr for InputBinding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
         if let Some(key_str) = s.strip_prefix("Key:") {
-            string_to_key(key_str).map(InputBinding::Key)
+            string_to_key(key_str)
+                .map(InputBinding::Key)
+                .ok_or_else(|| anyhow!("Unknown key name {key_str:?} in binding {s:?}"))
         } else if let Some(button_str) = s.strip_prefix("Mouse:") {
-            string_to_button(button_str).map(InputBinding::Mouse)
-        } else {
+            string_to_button(button_str)
+                .map(InputBinding::Mouse)
+                .ok_or_else(|| anyhow!("Unknown mouse button {button_str:?} in binding {s:?}"))
+        } else if s == "RightMouse" {
             // Legacy support for old configs
-            if s == "RightMouse" {
-                Some(InputBinding::Mouse(Button::Right))
-            } else {
-                string_to_key(s).map(InputBinding::Key)
-            }
+            Ok(InputBinding::Mouse(Button::Right))
+        } else {
+            // Legacy support for old configs that stored bare key names
+            string_to_key(s).map(InputBinding::Key).ok_or_else(|| {
+                anyhow!("Unknown binding {s:?}, expected \"Key:<name>\" or \"Mouse:<name>\"")
+            })
         }
     }
 }
 
+impl Serialize for InputBinding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for InputBinding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl Display for InputBinding {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -414,7 +1496,7 @@ pub fn string_to_key(s: &str) -> Option<Key> {
         "PlayPause" => Some(Key::PlayPause),
         "PlayCd" => Some(Key::PlayCd),
         "NextTrack" => Some(Key::NextTrack),
-        _ => None,
+        _ => parse_unknown_code(s).map(Key::Unknown),
     }
 }
 
@@ -435,10 +1517,15 @@ pub fn string_to_button(s: &str) -> Option<Button> {
         "Left" => Some(Button::Left),
         "Right" => Some(Button::Right),
         "Middle" => Some(Button::Middle),
-        _ => None,
+        _ => parse_unknown_code(s).map(Button::Unknown),
     }
 }
 
+/// Parse the `Unknown(code)` form written for raw key codes and buttons
+fn parse_unknown_code<T: FromStr>(s: &str) -> Option<T> {
+    s.strip_prefix("Unknown(")?.strip_suffix(')')?.parse().ok()
+}
+
 /// Get all available keys
 pub fn get_all_keys() -> Vec<Key> {
     vec![
@@ -574,3 +1661,390 @@ pub fn get_all_keys() -> Vec<Key> {
 pub fn get_all_buttons() -> Vec<Button> {
     vec![Button::Left, Button::Middle, Button::Right]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Resolve the defaults with `layers` applied in order, without reading any files
+    fn resolve_layers(layers: &[(ConfigLayer, &str)]) -> ResolvedConfig {
+        let mut resolved = ResolvedConfig::from_config(Config::default());
+        for (layer, contents) in layers {
+            resolved.apply(*layer, &toml::from_str(contents).unwrap());
+        }
+        resolved.config = resolved.resolved.clone().try_into().unwrap();
+        resolved
+    }
+
+    fn user_values(resolved: &ResolvedConfig, config: &Config) -> (toml::Table, toml::Table) {
+        let mut profile = toml::Table::new();
+        let table = resolved.user_values(&config.to_table().unwrap(), "", &mut profile);
+        (table, profile)
+    }
+
+    #[test]
+    fn unchanged_floats_are_not_user_values() {
+        let resolved = resolve_layers(&[
+            (ConfigLayer::Team, "timer_start = 19.3"),
+            (ConfigLayer::Profile, "expired_linger = 1.7"),
+        ]);
+
+        let (table, profile) = user_values(&resolved, &resolved.config);
+        assert!(table.is_empty(), "{table:?}");
+        assert!(profile.is_empty(), "{profile:?}");
+        assert_eq!(
+            resolved.source(&resolved.config.to_table().unwrap(), "timer_start"),
+            Some(ConfigLayer::Team)
+        );
+    }
+
+    #[test]
+    fn profile_edits_are_written_to_the_profile() {
+        let mut resolved = resolve_layers(&[
+            (ConfigLayer::User, "max_timers = 3"),
+            (
+                ConfigLayer::Profile,
+                "timer_start = 20.0\n[theme]\ntimer_font_size = 30.0",
+            ),
+        ]);
+        resolved.profile = Some("viper".to_string());
+
+        let mut config = resolved.config.clone();
+        config.timer_start = 25.5;
+        config.theme.timer_font_size = 32.0;
+        config.expired_linger += 1.0;
+
+        let (table, profile) = user_values(&resolved, &config);
+        assert_eq!(profile.get("timer_start"), Some(&toml::Value::Float(25.5)));
+        assert_eq!(
+            lookup(&profile, "theme.timer_font_size"),
+            Some(&toml::Value::Float(32.0))
+        );
+        assert!(!table.contains_key("timer_start"));
+        assert!(!table.contains_key("theme"));
+        assert!(table.contains_key("max_timers"));
+        assert!(table.contains_key("expired_linger"));
+    }
+
+    #[test]
+    fn later_profiles_override_single_values() {
+        let mut profiles: toml::Table =
+            toml::from_str("[viper]\ntimer_start = 20.0\nmax_timers = 4").unwrap();
+        merge_tables(
+            &mut profiles,
+            toml::from_str("[viper]\ntimer_start = 25.0\n[sova]\nmax_timers = 2").unwrap(),
+        );
+
+        let expected: toml::Table =
+            toml::from_str("[viper]\ntimer_start = 25.0\nmax_timers = 4\n[sova]\nmax_timers = 2")
+                .unwrap();
+        assert_eq!(profiles, expected);
+    }
+
+    const COMMENTED: &str = r#"# My settings
+timer_start = 19.3 # brimstone
+max_timers   = 3
+
+# Not a setting, but kept
+notes = "smokes"
+
+[theme]
+# Bigger text
+timer_font_size = 52.5
+"#;
+
+    /// Merge `table` into the document `existing` the way saving does
+    fn merged(existing: &str, table: &toml::Table) -> String {
+        let mut document: DocumentMut = existing.parse().unwrap();
+        let updated: DocumentMut = toml::to_string_pretty(table).unwrap().parse().unwrap();
+        merge_table(document.as_table_mut(), updated.as_table());
+        document.to_string()
+    }
+
+    #[test]
+    fn unchanged_document_round_trips() {
+        let resolved = resolve_layers(&[(ConfigLayer::User, COMMENTED)]);
+        let (table, _) = user_values(&resolved, &resolved.config);
+        assert_eq!(merged(COMMENTED, &table), COMMENTED);
+    }
+
+    #[test]
+    fn changed_values_keep_their_comments() {
+        let resolved = resolve_layers(&[(ConfigLayer::User, COMMENTED)]);
+        let mut config = resolved.config.clone();
+        config.timer_start = 20.5;
+        config.theme.timer_font_size = 40.0;
+
+        let (table, _) = user_values(&resolved, &config);
+        let expected = COMMENTED
+            .replace("19.3 #", "20.5 #")
+            .replace("= 52.5", "= 40.0");
+        assert_eq!(merged(COMMENTED, &table), expected);
+    }
+
+    #[test]
+    fn values_equal_ignores_formatting() {
+        let value = |s: &str| s.parse::<Value>().unwrap();
+        assert!(values_equal(&value("1.50"), &value("1.5")));
+        assert!(values_equal(&value("0x10"), &value("16")));
+        assert!(values_equal(&value("'a'"), &value(r#""a""#)));
+        assert!(values_equal(&value("[1,2]"), &value("[ 1, 2 ]")));
+        assert!(values_equal(
+            &value("{ a = 1, b = 2 }"),
+            &value("{b=2,a=1}")
+        ));
+        assert!(!values_equal(&value("1.0"), &value("1")));
+        assert!(!values_equal(&value("[1, 2]"), &value("[1]")));
+    }
+
+    #[test]
+    fn bindings_parse_with_prefixes() {
+        let parse = |s: &str| s.parse::<InputBinding>().unwrap();
+        assert_eq!(parse("Key:KeyE"), InputBinding::Key(Key::KeyE));
+        assert_eq!(
+            parse("Key:Unknown(200)"),
+            InputBinding::Key(Key::Unknown(200))
+        );
+        assert_eq!(parse("Mouse:Right"), InputBinding::Mouse(Button::Right));
+        assert_eq!(
+            parse("Mouse:Unknown(8)"),
+            InputBinding::Mouse(Button::Unknown(8))
+        );
+    }
+
+    #[test]
+    fn legacy_bindings_parse() {
+        let parse = |s: &str| s.parse::<InputBinding>().unwrap();
+        assert_eq!(parse("RightMouse"), InputBinding::Mouse(Button::Right));
+        assert_eq!(parse("Num4"), InputBinding::Key(Key::Num4));
+    }
+
+    #[test]
+    fn unknown_bindings_are_errors() {
+        for s in ["Key:Nope", "Mouse:Back", "Mouse:KeyE", "Nope", ""] {
+            assert!(s.parse::<InputBinding>().is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn bindings_round_trip() {
+        let bindings = get_all_keys()
+            .into_iter()
+            .map(InputBinding::Key)
+            .chain(get_all_buttons().into_iter().map(InputBinding::Mouse));
+        for binding in bindings {
+            assert_eq!(
+                binding.to_string().parse::<InputBinding>().unwrap(),
+                binding
+            );
+        }
+    }
+
+    #[test]
+    fn rounding_to_unit() {
+        assert_eq!(TimeRounding::Down.round(1_999, 1000), 1000);
+        assert_eq!(TimeRounding::Nearest.round(1_499, 1000), 1000);
+        assert_eq!(TimeRounding::Nearest.round(1_500, 1000), 2000);
+        assert_eq!(TimeRounding::Up.round(1_001, 1000), 2000);
+        assert_eq!(TimeRounding::Up.round(1_000, 1000), 1000);
+        assert_eq!(TimeRounding::Up.round(1, 10), 10);
+        for rounding in TimeRounding::ALL {
+            assert_eq!(rounding.round(0, 1000), 0);
+        }
+    }
+
+    #[test]
+    fn time_formats() {
+        assert_eq!(TimeFormat::Hundredths.format(5_670), "05.67");
+        assert_eq!(TimeFormat::Hundredths.format(123_450), "123.45");
+        assert_eq!(TimeFormat::Tenths.format(12_300), "12.3");
+        assert_eq!(TimeFormat::Seconds.format(5_000), "05");
+        assert_eq!(TimeFormat::Seconds.format(125_000), "125");
+        assert_eq!(TimeFormat::MinutesSeconds.format(125_000), "2:05");
+        assert_eq!(TimeFormat::MinutesSeconds.format(45_000), "0:45");
+        assert_eq!(TimeFormat::Auto.format(125_000), "2:05");
+        assert_eq!(TimeFormat::Auto.format(59_990), "59.99");
+    }
+
+    #[test]
+    fn auto_format_switches_precision_above_a_minute() {
+        let shown = |ms: i64, rounding: TimeRounding| {
+            let format = TimeFormat::Auto;
+            format.format(rounding.round(ms, format.unit_ms(ms, rounding)))
+        };
+        assert_eq!(shown(90_999, TimeRounding::Down), "1:30");
+        assert_eq!(shown(60_999, TimeRounding::Down), "1:00");
+        assert_eq!(shown(60_000, TimeRounding::Down), "1:00");
+        assert_eq!(shown(59_999, TimeRounding::Down), "59.99");
+        assert_eq!(shown(59_500, TimeRounding::Nearest), "1:00");
+        assert_eq!(shown(59_499, TimeRounding::Nearest), "59.50");
+        assert_eq!(shown(59_001, TimeRounding::Up), "1:00");
+        assert_eq!(shown(59_000, TimeRounding::Up), "59.00");
+    }
+
+    fn migrated(contents: &str) -> toml::Table {
+        let mut table = toml::from_str(contents).unwrap();
+        migrate_legacy_keys(&mut table);
+        table
+    }
+
+    #[test]
+    fn legacy_red_text_becomes_a_stage() {
+        let expected: toml::Table =
+            toml::from_str(r##"warning_stages = [{ threshold = 3.0, color = "#FF0000" }]"##)
+                .unwrap();
+        assert_eq!(migrated("red_text_threshold = 3"), expected);
+        assert_eq!(
+            migrated("enable_red_text = true\nred_text_threshold = 3.0"),
+            expected
+        );
+    }
+
+    #[test]
+    fn disabled_red_text_has_no_stages() {
+        let expected: toml::Table = toml::from_str("warning_stages = []").unwrap();
+        assert_eq!(
+            migrated("enable_red_text = false\nred_text_threshold = 3.0"),
+            expected
+        );
+    }
+
+    #[test]
+    fn migration_keeps_explicit_stages() {
+        let stages = r##"warning_stages = [{ threshold = 8.0, color = "#FFFF00" }]"##;
+        let expected: toml::Table = toml::from_str(stages).unwrap();
+        assert_eq!(
+            migrated(&format!("enable_red_text = true\n{stages}")),
+            expected
+        );
+        assert_eq!(
+            migrated("max_timers = 2"),
+            toml::from_str("max_timers = 2").unwrap()
+        );
+    }
+
+    #[test]
+    fn legacy_red_text_resolves() {
+        let resolved = resolve_layers(&[(
+            ConfigLayer::User,
+            "enable_red_text = true\nred_text_threshold = 7.5",
+        )]);
+        assert_eq!(
+            resolved.config.warning_stages,
+            [WarningStage {
+                threshold: 7.5,
+                color: Color32::RED
+            }]
+        );
+    }
+
+    const LEGACY_FILE: &str = r#"profile = "viper"
+enable_red_text = true
+red_text_threshold = 3.0
+start_key = "Key:Nope"
+"#;
+
+    /// Save `config` over `LEGACY_FILE` in a fresh directory named after the test, and
+    /// read the file back
+    fn save_over_legacy_file(
+        name: &str,
+        resolved: &ResolvedConfig,
+        config: &Config,
+    ) -> toml::Table {
+        let dir = std::env::temp_dir().join(format!(
+            "{}-config-{name}-{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(PROGRAM_CONFIG_NAME);
+        fs::write(&path, LEGACY_FILE).unwrap();
+
+        resolved.save_to(&path, config).unwrap();
+        let saved = read_table(&path).unwrap().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        saved
+    }
+
+    #[test]
+    fn save_after_failed_load_keeps_unloaded_keys() {
+        let resolved = ResolvedConfig {
+            error: Some("Unknown key name".to_string()),
+            ..ResolvedConfig::from_config(Config::default())
+        };
+        let config = Config {
+            max_timers: 5,
+            max_backups: 0,
+            ..Config::default()
+        };
+
+        let saved = save_over_legacy_file("failed", &resolved, &config);
+        for key in [
+            PROFILE_KEY,
+            LEGACY_RED_TEXT_KEY,
+            LEGACY_RED_TEXT_THRESHOLD_KEY,
+        ] {
+            assert!(saved.contains_key(key), "{key} was removed");
+        }
+        assert_eq!(saved.get("max_timers"), Some(&toml::Value::Integer(5)));
+    }
+
+    #[test]
+    fn save_after_load_removes_migrated_keys() {
+        let resolved = ResolvedConfig::from_config(Config::default());
+        let config = Config {
+            max_backups: 0,
+            ..Config::default()
+        };
+
+        let saved = save_over_legacy_file("loaded", &resolved, &config);
+        for key in [
+            PROFILE_KEY,
+            LEGACY_RED_TEXT_KEY,
+            LEGACY_RED_TEXT_THRESHOLD_KEY,
+        ] {
+            assert!(!saved.contains_key(key), "{key} was kept");
+        }
+    }
+
+    fn env_layer(vars: &[(&str, &str)]) -> toml::Table {
+        let vars = vars
+            .iter()
+            .map(|(name, value)| (format!("{ENV_PREFIX}{name}"), value.to_string()));
+        env_table(vars, &Config::default().to_table().unwrap())
+    }
+
+    #[test]
+    fn env_values_keep_the_type_they_override() {
+        let env = env_layer(&[
+            ("SUBTEXT_STRING", "2"),
+            ("EXPIRED_TEXT", "true"),
+            ("TIMER_START", "15"),
+            ("MAX_TIMERS", "4"),
+            ("THEME__TIMER_FONT_SIZE", "40.5"),
+            ("PROFILE", "1"),
+            ("NOT_A_SETTING", "not toml"),
+        ]);
+
+        let expected: toml::Table = toml::from_str(
+            r#"subtext_string = "2"
+expired_text = "true"
+timer_start = 15.0
+max_timers = 4
+profile = "1"
+not_a_setting = "not toml"
+theme = { timer_font_size = 40.5 }"#,
+        )
+        .unwrap();
+        assert_eq!(env, expected);
+    }
+
+    #[test]
+    fn numeric_env_string_resolves() {
+        let mut resolved = ResolvedConfig::from_config(Config::default());
+        resolved.apply(ConfigLayer::Env, &env_layer(&[("SUBTEXT_STRING", "2")]));
+        let config: Config = resolved.resolved.clone().try_into().unwrap();
+        assert_eq!(config.subtext_string, "2");
+    }
+}
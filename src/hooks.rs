@@ -0,0 +1,259 @@
+use crate::config::HookConfig;
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+// Timer details are passed in environment variables starting with this
+const ENV_PREFIX: &str = "SMOKE_TIMER_";
+// How often a running command is checked on while waiting for it to finish
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const MAX_TIMEOUT_SECS: f32 = 24.0 * 60.0 * 60.0;
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    Start,
+    Expire,
+    Cancel,
+}
+
+impl HookEvent {
+    fn key(self) -> &'static str {
+        match self {
+            HookEvent::Start => "start",
+            HookEvent::Expire => "expire",
+            HookEvent::Cancel => "cancel",
+        }
+    }
+
+    fn command(self, hooks: &HookConfig) -> &str {
+        match self {
+            HookEvent::Start => &hooks.on_start,
+            HookEvent::Expire => &hooks.on_expire,
+            HookEvent::Cancel => &hooks.on_cancel,
+        }
+    }
+}
+
+/// The timer an event happened to, as passed to its command
+#[derive(Clone, Debug, Serialize)]
+pub struct TimerDetails {
+    pub event: HookEvent,
+    pub label: String,
+    /// Seconds the timer started with
+    pub duration: f32,
+    /// Seconds the timer had left when the event happened
+    pub remaining: f32,
+    /// The number shown with the timer
+    pub index: usize,
+}
+
+/// Run the command for the event in `details` on its own thread, if one is set
+pub fn run(hooks: &HookConfig, details: TimerDetails) {
+    let command = details.event.command(hooks).trim().to_string();
+    if command.is_empty() {
+        return;
+    }
+
+    let timeout = timeout(hooks);
+    std::thread::spawn(move || {
+        if let Err(e) = run_command(&command, &details, timeout) {
+            eprintln!("Failed to run {} hook: {e:?}", details.event.key());
+        }
+    });
+}
+
+/// Get how long a command may run, using the default for a timeout that isn't a number
+fn timeout(hooks: &HookConfig) -> Duration {
+    let secs = if hooks.timeout.is_finite() {
+        hooks.timeout
+    } else {
+        HookConfig::default().timeout
+    };
+    Duration::from_secs_f32(secs.clamp(0.0, MAX_TIMEOUT_SECS))
+}
+
+/// Run `command` through the shell with the timer details in its environment and as JSON
+/// on its stdin, killing it if it outlasts `timeout`
+fn run_command(command: &str, details: &TimerDetails, timeout: Duration) -> Result<()> {
+    let json = serde_json::to_string(details).with_context(|| "Failed to serialize timer")?;
+
+    let mut child = shell(command)
+        .env(format!("{ENV_PREFIX}EVENT"), details.event.key())
+        .env(format!("{ENV_PREFIX}LABEL"), &details.label)
+        .env(
+            format!("{ENV_PREFIX}DURATION"),
+            details.duration.to_string(),
+        )
+        .env(
+            format!("{ENV_PREFIX}REMAINING"),
+            details.remaining.to_string(),
+        )
+        .env(format!("{ENV_PREFIX}INDEX"), details.index.to_string())
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start {command:?}"))?;
+
+    // Commands that don't read their input may close it early, which is fine
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(json.as_bytes());
+    }
+
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child
+            .try_wait()
+            .with_context(|| format!("Failed to wait for {command:?}"))?
+        {
+            if !status.success() {
+                bail!("{command:?} exited with {status}");
+            }
+            return Ok(());
+        }
+
+        if started.elapsed() >= timeout {
+            child
+                .kill()
+                .with_context(|| format!("Failed to kill {command:?}"))?;
+            let _ = child.wait();
+            bail!("{command:?} timed out after {:.1}s", timeout.as_secs_f32());
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    let mut shell = Command::new("cmd");
+    // Passed as is, since cmd doesn't understand the escaping `arg` gives embedded quotes.
+    // Without a console of our own, cmd would otherwise open one and steal focus
+    shell
+        .arg("/C")
+        .raw_arg(command)
+        .creation_flags(CREATE_NO_WINDOW);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hooks(timeout: f32) -> HookConfig {
+        HookConfig {
+            timeout,
+            ..HookConfig::default()
+        }
+    }
+
+    #[test]
+    fn timeout_is_clamped() {
+        assert_eq!(timeout(&hooks(2.5)), Duration::from_millis(2500));
+        assert_eq!(timeout(&hooks(-1.0)), Duration::ZERO);
+        assert_eq!(
+            timeout(&hooks(1e12)),
+            Duration::from_secs_f32(MAX_TIMEOUT_SECS)
+        );
+    }
+
+    #[test]
+    fn non_finite_timeout_uses_default() {
+        let default = Duration::from_secs_f32(HookConfig::default().timeout);
+        assert_eq!(timeout(&hooks(f32::NAN)), default);
+        assert_eq!(timeout(&hooks(f32::INFINITY)), default);
+        assert_eq!(timeout(&hooks(f32::NEG_INFINITY)), default);
+    }
+
+    #[cfg(unix)]
+    fn details() -> TimerDetails {
+        TimerDetails {
+            event: HookEvent::Expire,
+            label: "Smoke 2".to_string(),
+            duration: 19.5,
+            remaining: 0.0,
+            index: 2,
+        }
+    }
+
+    #[cfg(unix)]
+    /// Get a path named after the test for a command to write its output to
+    fn output_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "{}-hook-{name}-{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[cfg(unix)]
+    fn read_output(path: &std::path::Path) -> String {
+        let output = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        output
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn details_are_passed_in_the_environment() {
+        let path = output_path("env");
+        let command = format!(
+            "printf '%s\\n' \"$SMOKE_TIMER_EVENT\" \"$SMOKE_TIMER_LABEL\" \
+             \"$SMOKE_TIMER_DURATION\" \"$SMOKE_TIMER_REMAINING\" \"$SMOKE_TIMER_INDEX\" > '{}'",
+            path.display()
+        );
+        run_command(&command, &details(), Duration::from_secs(5)).unwrap();
+        assert_eq!(read_output(&path), "expire\nSmoke 2\n19.5\n0\n2\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn details_are_passed_as_json_on_stdin() {
+        let path = output_path("stdin");
+        let command = format!("cat > '{}'", path.display());
+        run_command(&command, &details(), Duration::from_secs(5)).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&read_output(&path)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "event": "expire",
+                "label": "Smoke 2",
+                "duration": 19.5,
+                "remaining": 0.0,
+                "index": 2,
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_past_their_timeout_are_killed() {
+        let started = Instant::now();
+        let result = run_command("sleep 10", &details(), Duration::from_millis(100));
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("timed out"), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_commands_are_errors() {
+        let result = run_command("exit 3", &details(), Duration::from_secs(5));
+        assert!(result.is_err());
+    }
+}
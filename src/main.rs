@@ -1,17 +1,34 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod audio;
+mod backup;
 mod config;
+mod hooks;
+mod overlay;
+mod session_log;
+mod stats;
 
+use audio::{AudioCues, Cue};
+use backup::{Backup, BackupChange};
 use config::{
-    Config, InputBinding, button_to_string, get_all_buttons, get_all_keys, key_to_string,
+    Anchor, AudioConfig, AudioOutput, Config, InputBinding, OverlayLayout, PositionUnit,
+    ResolvedConfig, SUBTEXT_PLACEHOLDERS, SessionLogFormat, SubtextPosition, Theme, TimeFormat,
+    TimeRounding, TimerStyle, WarningStage, button_to_string, get_all_buttons, get_all_keys,
+    key_to_string,
 };
 use display_info::DisplayInfo;
 use eframe::{App, Error, NativeOptions};
 use egui::{
-    CentralPanel, Color32, ComboBox, Context, DragValue, FontId, IconData, Margin, Rect, Rgba,
-    ScrollArea, Sense, Slider, ViewportBuilder, ViewportCommand, Visuals, viewport::WindowLevel,
+    Align2, CentralPanel, Color32, ComboBox, Context, DragValue, FontId, IconData, Margin, Pos2,
+    Rect, Rgba, ScrollArea, Sense, Slider, Stroke, StrokeKind, TextureHandle, Ui, UiBuilder, Vec2,
+    ViewportBuilder, ViewportCommand, Visuals, viewport::WindowLevel,
 };
+use hooks::{HookEvent, TimerDetails};
+use overlay::{OverlayRenderer, StartOutcome, Timer, load_fonts, load_timer_icon};
 use rdev::{Button, Event, EventType, Key, listen};
+use session_log::{SessionEvent, SessionLog};
+use stats::SessionStats;
+use std::path::{Path, PathBuf};
 use std::sync::{
     Arc,
     mpsc::{self, Receiver},
@@ -20,8 +37,17 @@ use std::time::{Duration, Instant};
 
 static APP_TITLE: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
 
+// How long the overlay shows that the custom font failed to load
+const FONT_WARNING_DURATION: Duration = Duration::from_secs(10);
+// Horizontal scale and row height of the session timeline
+const TIMELINE_POINTS_PER_SECOND: f32 = 8.0;
+const TIMELINE_ROW_HEIGHT: f32 = 14.0;
+const HOOK_HINT: &str = "Shell command, run with SMOKE_TIMER_EVENT, SMOKE_TIMER_LABEL, \
+    SMOKE_TIMER_DURATION, SMOKE_TIMER_REMAINING and SMOKE_TIMER_INDEX set and the same details \
+    as JSON on stdin";
+
 fn main() -> Result<(), Error> {
-    let config = Config::load();
+    let resolved = ResolvedConfig::load();
 
     let window_icon = load_icon();
 
@@ -38,7 +64,7 @@ fn main() -> Result<(), Error> {
     eframe::run_native(
         APP_TITLE,
         options,
-        Box::new(move |_cc| Ok(Box::new(MainApp::new(config)))),
+        Box::new(move |cc| Ok(Box::new(MainApp::new(&cc.egui_ctx, resolved)))),
     )
 }
 
@@ -58,10 +84,95 @@ fn load_icon() -> Arc<IconData> {
     })
 }
 
+/// Get every connected display
+fn get_displays() -> Vec<DisplayInfo> {
+    DisplayInfo::all().unwrap_or_else(|e| {
+        eprintln!("Failed to get display info: {e:?}");
+        Vec::new()
+    })
+}
+
+/// Find the display that the overlay is configured to use. Falls back to the primary
+/// display, then to the first display, if it isn't connected
+fn find_display<'a>(config: &Config, displays: &'a [DisplayInfo]) -> Option<&'a DisplayInfo> {
+    if !config.monitor_name.is_empty() {
+        let found = displays
+            .get(config.monitor_index)
+            .filter(|d| d.name == config.monitor_name)
+            .or_else(|| displays.iter().find(|d| d.name == config.monitor_name));
+        if found.is_some() {
+            return found;
+        }
+        eprintln!(
+            "Display {:?} isn't connected, falling back to the primary display",
+            config.monitor_name
+        );
+    }
+
+    // Try to find primary display, otherwise fallback to first display
+    displays.iter().find(|d| d.is_primary).or(displays.first())
+}
+
+/// Get a display's rectangle in physical pixels
+fn display_rect(display: &DisplayInfo) -> Rect {
+    Rect::from_min_size(
+        egui::pos2(display.x as f32, display.y as f32),
+        egui::vec2(display.width as f32, display.height as f32),
+    )
+}
+
+fn describe_display(index: usize, display: &DisplayInfo) -> String {
+    let primary = if display.is_primary { ", primary" } else { "" };
+    format!(
+        "{index}: {} ({}x{}{primary})",
+        display.name, display.width, display.height
+    )
+}
+
+/// Draw a session's timers as bars along a timeline, one row for each timer that was
+/// running at once, with dropped activations marked in red
+fn draw_timeline(ui: &mut Ui, stats: &SessionStats) {
+    let width = (stats.length * TIMELINE_POINTS_PER_SECOND).max(ui.available_width());
+    let height = stats.lanes().max(1) as f32 * TIMELINE_ROW_HEIGHT + TIMELINE_ROW_HEIGHT;
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(width, height), Sense::hover());
+    let painter = ui.painter_at(rect);
+    let x = |secs: f32| rect.left() + secs * TIMELINE_POINTS_PER_SECOND;
+
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+    for bar in &stats.timeline {
+        let top = rect.top() + bar.lane as f32 * TIMELINE_ROW_HEIGHT;
+        let bar_rect = Rect::from_x_y_ranges(
+            x(bar.start)..=x(bar.end),
+            top + 2.0..=top + TIMELINE_ROW_HEIGHT - 2.0,
+        );
+        let color = if bar.evicted {
+            Color32::ORANGE
+        } else {
+            Color32::LIGHT_BLUE
+        };
+        painter.rect_filled(bar_rect, 2.0, color);
+    }
+    for &secs in &stats.dropped_at {
+        painter.vline(x(secs), rect.y_range(), Stroke::new(1.5, Color32::RED));
+    }
+
+    // Label every minute along the bottom
+    let axis_top = rect.bottom() - TIMELINE_ROW_HEIGHT;
+    for minute in 0..=(stats.length / 60.0) as usize {
+        painter.text(
+            egui::pos2(x(minute as f32 * 60.0) + 2.0, axis_top),
+            Align2::LEFT_TOP,
+            format!("{minute}m"),
+            FontId::proportional(10.0),
+            ui.visuals().weak_text_color(),
+        );
+    }
+}
+
 // Main app that manages state transitions
 enum AppState {
-    Config(ConfigState),
-    Timer(TimerState),
+    Config(Box<ConfigState>),
+    Timer(Box<TimerState>),
 }
 
 struct MainApp {
@@ -69,9 +180,12 @@ struct MainApp {
 }
 
 impl MainApp {
-    fn new(config: Config) -> Self {
+    fn new(ctx: &Context, resolved: ResolvedConfig) -> Self {
+        let mut config_state = ConfigState::new(resolved.config.clone(), resolved);
+        config_state.font_warning = load_fonts(ctx, &config_state.config.theme.font_path);
+
         Self {
-            state: AppState::Config(ConfigState::new(config)),
+            state: AppState::Config(Box::new(config_state)),
         }
     }
 }
@@ -87,19 +201,30 @@ impl App for MainApp {
     fn update(&mut self, ctx: &Context, frame: &mut eframe::Frame) {
         match &mut self.state {
             AppState::Config(config_state) => {
-                if let Some(config) = config_state.update(ctx, frame) {
+                if let Some((config, repositioning)) = config_state.update(ctx, frame) {
                     // Transition to timer state
+                    let font_warning = load_fonts(ctx, &config.theme.font_path);
+
                     // First reconfigure the viewport
+                    // The timer state sizes and positions the overlay on its first frame
                     ctx.send_viewport_cmd(ViewportCommand::Title(APP_TITLE.to_string()));
-                    ctx.send_viewport_cmd(ViewportCommand::InnerSize([1000.0, 200.0].into()));
                     ctx.send_viewport_cmd(ViewportCommand::Resizable(false));
                     ctx.send_viewport_cmd(ViewportCommand::WindowLevel(WindowLevel::AlwaysOnTop));
                     ctx.send_viewport_cmd(ViewportCommand::Decorations(false));
-                    ctx.send_viewport_cmd(ViewportCommand::OuterPosition(
-                        [config.initial_pos.0, config.initial_pos.1].into(),
-                    ));
 
-                    self.state = AppState::Timer(TimerState::new(config));
+                    let resolved = config_state.resolved.clone();
+                    let icon = (!config.icon_path.is_empty())
+                        .then(|| load_timer_icon(ctx, &config.icon_path))
+                        .and_then(|icon| {
+                            icon.map_err(|e| eprintln!("Failed to load timer icon: {e:?}"))
+                                .ok()
+                        });
+
+                    let mut timer_state = TimerState::new(config, resolved, repositioning);
+                    timer_state.icon = icon;
+                    timer_state.font_warning = font_warning
+                        .map(|warning| (warning, Instant::now() + FONT_WARNING_DURATION));
+                    self.state = AppState::Timer(Box::new(timer_state));
 
                     ctx.request_repaint();
                 }
@@ -121,93 +246,70 @@ enum InputType {
 
 struct ConfigState {
     config: Config,
+    resolved: ResolvedConfig,
+    // The config as a table for the source labels, serialized once per frame
+    config_table: toml::Table,
+    // Where the config file is and whether it's next to the executable, neither of which
+    // changes while the window is open
+    config_path: Result<PathBuf, String>,
+    portable: bool,
     start_input_type: InputType,
-    start_key_selected: usize,
-    start_button_selected: usize,
+    start_key_selected: Key,
+    start_button_selected: Button,
     confirm_input_type: InputType,
-    confirm_key_selected: usize,
-    confirm_button_selected: usize,
+    confirm_key_selected: Key,
+    confirm_button_selected: Button,
     available_keys: Vec<Key>,
     available_buttons: Vec<Button>,
-    cancelable_keys_selected: Vec<usize>,
-    cancelable_buttons_selected: Vec<usize>,
+    displays: Vec<DisplayInfo>,
+    backups: Vec<Backup>,
+    selected_backup: Option<usize>,
+    backup_changes: Vec<BackupChange>,
+    font_warning: Option<String>,
+    // Remaining seconds of the newest timer in the preview
+    preview_remaining: f32,
+    // Icon shown in the preview, with the path it was loaded from
+    preview_icon: Option<(String, Option<TextureHandle>)>,
+    // Simulated activations, which replace the scrubbed timers in the preview while any
+    // are running
+    test_detector: SequenceDetector,
+    test_timers: Vec<Timer>,
+    test_status: String,
+    // Plays the sounds tried out with the "Play" buttons, with the settings it was built
+    // from so it's only rebuilt when they change
+    audio_test: Option<(AudioConfig, AudioCues)>,
+    // Where session logs are written, looked up once rather than every frame
+    log_dir: Result<PathBuf, String>,
+    // Loaded from the session logs when the stats are first asked for
+    session_stats: Option<Vec<SessionStats>>,
+    selected_session: Option<usize>,
 }
 
 impl ConfigState {
-    fn new(config: Config) -> Self {
+    fn new(config: Config, resolved: ResolvedConfig) -> Self {
         let available_keys = get_all_keys();
         let available_buttons = get_all_buttons();
 
-        // Parse start key/button
-        let start_binding = InputBinding::from_string(&config.start_key);
-        let (start_input_type, start_key_selected, start_button_selected) = match start_binding {
-            Some(InputBinding::Key(key)) => {
-                let idx = available_keys
-                    .iter()
-                    .position(|k| key_to_string(k) == key_to_string(&key))
-                    .unwrap_or(0);
-                (InputType::Keyboard, idx, 0)
-            }
-            Some(InputBinding::Mouse(button)) => {
-                let idx = available_buttons
-                    .iter()
-                    .position(|b| button_to_string(b) == button_to_string(&button))
-                    .unwrap_or(0);
-                (InputType::Mouse, 0, idx)
-            }
-            None => (InputType::Keyboard, 0, 0),
+        // Split each binding into its input type, keeping a selection for the other type
+        // around so switching between keyboard and mouse doesn't lose anything
+        let split_binding = |binding: InputBinding| match binding {
+            InputBinding::Key(key) => (InputType::Keyboard, key, available_buttons[0]),
+            InputBinding::Mouse(button) => (InputType::Mouse, available_keys[0], button),
         };
 
-        // Parse confirm key/button
-        let confirm_binding = InputBinding::from_string(&config.confirm_key);
+        let (start_input_type, start_key_selected, start_button_selected) =
+            split_binding(config.start_key);
         let (confirm_input_type, confirm_key_selected, confirm_button_selected) =
-            match confirm_binding {
-                Some(InputBinding::Key(key)) => {
-                    let idx = available_keys
-                        .iter()
-                        .position(|k| key_to_string(k) == key_to_string(&key))
-                        .unwrap_or(0);
-                    (InputType::Keyboard, idx, 0)
-                }
-                Some(InputBinding::Mouse(button)) => {
-                    let idx = available_buttons
-                        .iter()
-                        .position(|b| button_to_string(b) == button_to_string(&button))
-                        .unwrap_or(0);
-                    (InputType::Mouse, 0, idx)
-                }
-                None => (InputType::Mouse, 0, 2), // Default to right mouse
-            };
-
-        // Find indices of cancelable keys and buttons
-        let mut cancelable_keys_selected = Vec::new();
-        let mut cancelable_buttons_selected = Vec::new();
-
-        for key_str in &config.cancelable_keys {
-            if let Some(binding) = InputBinding::from_string(key_str) {
-                match binding {
-                    InputBinding::Key(k) => {
-                        if let Some(idx) = available_keys
-                            .iter()
-                            .position(|key| key_to_string(key) == key_to_string(&k))
-                        {
-                            cancelable_keys_selected.push(idx);
-                        }
-                    }
-                    InputBinding::Mouse(b) => {
-                        if let Some(idx) = available_buttons
-                            .iter()
-                            .position(|button| button_to_string(button) == button_to_string(&b))
-                        {
-                            cancelable_buttons_selected.push(idx);
-                        }
-                    }
-                }
-            }
-        }
+            split_binding(config.confirm_key);
 
         Self {
+            preview_remaining: config.timer_start,
+            test_detector: SequenceDetector::new(&config),
+            config_table: config.to_table().unwrap_or_default(),
+            config_path: Config::get_default_config_path().map_err(|e| e.to_string()),
+            portable: Config::get_portable_dir().is_some(),
             config,
+            resolved,
             start_input_type,
             start_key_selected,
             start_button_selected,
@@ -216,63 +318,368 @@ impl ConfigState {
             confirm_button_selected,
             available_keys,
             available_buttons,
-            cancelable_keys_selected,
-            cancelable_buttons_selected,
+            displays: get_displays(),
+            backups: backup::list().unwrap_or_else(|e| {
+                eprintln!("Failed to list config backups: {e:?}");
+                Vec::new()
+            }),
+            selected_backup: None,
+            backup_changes: Vec::new(),
+            font_warning: None,
+            preview_icon: None,
+            test_timers: Vec::new(),
+            test_status: String::new(),
+            audio_test: None,
+            log_dir: session_log::log_dir().map_err(|e| e.to_string()),
+            session_stats: None,
+            selected_session: None,
+        }
+    }
+
+    /// Feed synthetic presses into a sequence detector set up like the overlay's, starting
+    /// a test timer if they complete the activation sequence
+    fn simulate_inputs(&mut self, bindings: &[InputBinding]) {
+        // Pick up binding changes made since the last test, keeping the sequence progress
+        let waiting_for_confirm = self.test_detector.waiting_for_confirm;
+        self.test_detector = SequenceDetector::new(&self.config);
+        self.test_detector.waiting_for_confirm = waiting_for_confirm;
+
+        let mut end = None;
+        for &binding in bindings {
+            end = self
+                .test_detector
+                .on_input(InputEvent::from_binding(binding))
+                .or(end);
+        }
+
+        self.test_status = if end == Some(SequenceEnd::Completed) {
+            self.spawn_test_timer();
+            format!("Sequence completed: {}", self.test_status)
+        } else if end == Some(SequenceEnd::Canceled) {
+            "Sequence canceled".to_string()
+        } else if self.test_detector.waiting_for_confirm {
+            "Waiting for the confirm key".to_string()
+        } else {
+            "Waiting for the start key".to_string()
+        };
+    }
+
+    /// Start a test timer straight away, the way the overlay does on an activation
+    fn spawn_test_timer(&mut self) {
+        self.test_status = match overlay::start_timer(&mut self.test_timers, &self.config) {
+            StartOutcome::Started => "timer started",
+            StartOutcome::Overwrote { .. } => "the oldest timer was overwritten",
+            StartOutcome::Dropped => "dropped, every slot is taken",
+        }
+        .to_string();
+    }
+
+    /// Simulate a full overlay for the preview, with the newest timer at the scrubbed time
+    /// and each older one started evenly further in the past
+    fn preview_timers(&self) -> Vec<Timer> {
+        let config = &self.config;
+        let duration_ms = (config.timer_start * 1000.0) as u64;
+        let remaining_ms = (self.preview_remaining * 1000.0) as i64;
+        let step_ms = duration_ms as i64 / config.max_timers.max(1) as i64;
+        let linger_ms = (config.expired_linger * 1000.0) as i64;
+
+        // Oldest first, the same order timers are started in
+        let mut timers: Vec<Timer> = (0..config.max_timers)
+            .rev()
+            .map(|age| remaining_ms - age as i64 * step_ms)
+            .filter(|&remaining| remaining > 0 || -remaining < linger_ms)
+            .enumerate()
+            .map(|(slot, remaining)| Timer::with_remaining(duration_ms, remaining, slot))
+            .collect();
+        if config.add_new_on_left {
+            timers.reverse();
         }
+        timers
     }
 
-    // Returns Some(config) when ready to transition to timer
-    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) -> Option<Config> {
+    /// Draw the preview with the overlay renderer and the current, unsaved settings
+    fn draw_preview(&mut self, ctx: &Context, ui: &mut Ui) {
+        let icon_path = &self.config.icon_path;
+        if self.preview_icon.as_ref().map(|(path, _)| path) != Some(icon_path) {
+            let icon = (!icon_path.is_empty())
+                .then(|| load_timer_icon(ctx, icon_path).ok())
+                .flatten();
+            self.preview_icon = Some((icon_path.clone(), icon));
+        }
+
+        let renderer = OverlayRenderer {
+            config: &self.config,
+            profile: self.resolved.profile.as_deref(),
+            icon: self
+                .preview_icon
+                .as_ref()
+                .and_then(|(_, icon)| icon.as_ref()),
+        };
+        let size = renderer.overlay_size(ctx);
+        overlay::remove_expired(&mut self.test_timers, &self.config);
+        let scrubbed;
+        let timers = if self.test_timers.is_empty() {
+            scrubbed = self.preview_timers();
+            &scrubbed
+        } else {
+            ctx.request_repaint();
+            &self.test_timers
+        };
+
+        ScrollArea::horizontal()
+            .id_salt("preview_scroll")
+            .show(ui, |ui| {
+                let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+                // Stand-in for the game behind the transparent overlay
+                ui.painter().rect_filled(rect, 0.0, Color32::from_gray(60));
+                ui.scope_builder(UiBuilder::new().max_rect(rect), |ui| {
+                    renderer.draw(ui, timers);
+                });
+            });
+    }
+
+    /// Convert the overlay offset after its unit was switched, so the overlay stays put
+    fn convert_offset_unit(&mut self) {
+        let Some(display) = find_display(&self.config, &self.displays) else {
+            return;
+        };
+
+        let display_size = display_rect(display).size() / display.scale_factor;
+        let offset = Vec2::from(self.config.initial_pos);
+        let converted = match self.config.position_unit {
+            PositionUnit::Pixels => offset / 100.0 * display_size,
+            PositionUnit::Percent => offset / display_size * 100.0,
+        };
+        self.config.initial_pos = (converted.x, converted.y);
+    }
+
+    /// Show which config layer the value at `path` currently comes from
+    fn source_label(&self, ui: &mut Ui, path: &str) {
+        let text = match self.resolved.source(&self.config_table, path) {
+            Some(layer) => format!("({layer})"),
+            None => "(edited)".to_string(),
+        };
+        ui.weak(text);
+    }
+
+    // Returns Some((config, repositioning)) when ready to transition to timer
+    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) -> Option<(Config, bool)> {
         let mut should_start = false;
+        let mut should_reposition = false;
         let mut should_reset = false;
+        let mut profile_change = None;
+        let mut backup_selection = None;
+        let mut restore_backup = None;
+        self.config_table = self.config.to_table().unwrap_or_default();
 
         CentralPanel::default().show(ctx, |ui| {
             ui.heading("Timer Configuration");
+            match &self.config_path {
+                Ok(path) if self.portable => {
+                    ui.weak(format!("Portable mode: {}", path.display()));
+                }
+                Ok(path) => {
+                    ui.weak(path.display().to_string());
+                }
+                Err(e) => {
+                    ui.weak(format!("Failed to get config path: {e}"));
+                }
+            }
+            if let Some(error) = &self.resolved.error {
+                ui.colored_label(
+                    Color32::RED,
+                    format!("Failed to load config, showing the defaults: {error}"),
+                );
+            }
             ui.add_space(10.0);
 
             ScrollArea::vertical().show(ui, |ui| {
                 // Allocate remaining space to force full width
                 ui.allocate_space(egui::vec2(ui.available_width(), 0.0));
 
-                // Initial Position
+                // Profile
+                if !self.resolved.profiles.is_empty() {
+                    ui.group(|ui| {
+                        ui.label("Profile");
+                        let mut selected = self.resolved.profile.clone();
+                        ComboBox::from_id_salt("profile_combo")
+                            .selected_text(selected.as_deref().unwrap_or("(none)"))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut selected, None, "(none)");
+                                for profile in &self.resolved.profiles {
+                                    ui.selectable_value(
+                                        &mut selected,
+                                        Some(profile.clone()),
+                                        profile,
+                                    );
+                                }
+                            });
+                        if selected != self.resolved.profile {
+                            profile_change = Some(selected);
+                        }
+                        ui.label("(Switching profiles discards unsaved changes)");
+                    });
+                    ui.add_space(10.0);
+                }
+
+                // Overlay Position
                 ui.group(|ui| {
-                    ui.label("Initial Window Position");
                     ui.horizontal(|ui| {
-                        ui.label("X:");
-                        ui.add(DragValue::new(&mut self.config.initial_pos.0).speed(1.0));
-                        ui.label("Y:");
-                        ui.add(DragValue::new(&mut self.config.initial_pos.1).speed(1.0));
-                    });
-                    ui.horizontal(|ui| {
-                        if ui.button("Set to Primary Monitor Top-Left").clicked() {
-                            match DisplayInfo::all() {
-                                Ok(displays) => {
-                                    // Try to find primary display
-                                    if let Some(primary) = displays.iter().find(|d| d.is_primary) {
-                                        self.config.initial_pos =
-                                            (primary.x as f32, primary.y as f32);
-                                    } else if let Some(first) = displays.first() {
-                                        // Fallback to first display
-                                        self.config.initial_pos = (first.x as f32, first.y as f32);
-                                    } else {
-                                        // Final fallback
-                                        self.config.initial_pos = (0.0, 0.0);
+                        ui.label("Overlay Position");
+                        self.source_label(ui, "anchor");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Display:");
+                        let selected = if self.config.monitor_name.is_empty() {
+                            "Primary Display".to_string()
+                        } else {
+                            format!(
+                                "{}: {}",
+                                self.config.monitor_index, self.config.monitor_name
+                            )
+                        };
+                        ComboBox::from_id_salt("display_combo")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                if ui
+                                    .selectable_label(
+                                        self.config.monitor_name.is_empty(),
+                                        "Primary Display",
+                                    )
+                                    .clicked()
+                                {
+                                    self.config.monitor_name.clear();
+                                    self.config.monitor_index = 0;
+                                }
+                                for (i, display) in self.displays.iter().enumerate() {
+                                    let is_selected = self.config.monitor_name == display.name
+                                        && self.config.monitor_index == i;
+                                    if ui
+                                        .selectable_label(is_selected, describe_display(i, display))
+                                        .clicked()
+                                    {
+                                        self.config.monitor_name = display.name.clone();
+                                        self.config.monitor_index = i;
                                     }
                                 }
-                                Err(e) => {
-                                    eprintln!("Failed to get display info: {e:?}");
-                                    self.config.initial_pos = (0.0, 0.0);
+                            });
+                        if ui.button("Refresh").clicked() {
+                            self.displays = get_displays();
+                        }
+                        self.source_label(ui, "monitor_name");
+                    });
+                    if !self.config.monitor_name.is_empty()
+                        && !self
+                            .displays
+                            .iter()
+                            .any(|d| d.name == self.config.monitor_name)
+                    {
+                        ui.colored_label(
+                            Color32::YELLOW,
+                            format!(
+                                "Display {:?} isn't connected, the primary display will be used",
+                                self.config.monitor_name
+                            ),
+                        );
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("Anchor:");
+                        ComboBox::from_id_salt("anchor_combo")
+                            .selected_text(self.config.anchor.to_string())
+                            .show_ui(ui, |ui| {
+                                for anchor in Anchor::ALL {
+                                    ui.selectable_value(
+                                        &mut self.config.anchor,
+                                        anchor,
+                                        anchor.to_string(),
+                                    );
                                 }
+                            });
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Offset Unit:");
+                        let previous_unit = self.config.position_unit;
+                        ui.radio_value(
+                            &mut self.config.position_unit,
+                            PositionUnit::Pixels,
+                            "Pixels",
+                        );
+                        ui.radio_value(
+                            &mut self.config.position_unit,
+                            PositionUnit::Percent,
+                            "Percent of Display",
+                        );
+                        if self.config.position_unit != previous_unit {
+                            self.convert_offset_unit();
+                        }
+                        self.source_label(ui, "position_unit");
+                    });
+                    ui.horizontal(|ui| {
+                        let suffix = match self.config.position_unit {
+                            PositionUnit::Pixels => " px",
+                            PositionUnit::Percent => " %",
+                        };
+                        ui.label("Offset X:");
+                        ui.add(
+                            DragValue::new(&mut self.config.initial_pos.0)
+                                .speed(1.0)
+                                .suffix(suffix),
+                        );
+                        ui.label("Y:");
+                        ui.add(
+                            DragValue::new(&mut self.config.initial_pos.1)
+                                .speed(1.0)
+                                .suffix(suffix),
+                        );
+                        self.source_label(ui, "initial_pos");
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Snap to Anchor").clicked() {
+                            self.config.initial_pos = (0.0, 0.0);
+                        }
+                        if ui
+                            .button("Drag to Position")
+                            .on_hover_text("Save and start the overlay in reposition mode")
+                            .clicked()
+                        {
+                            if let Err(e) = self.resolved.save(&self.config) {
+                                eprintln!("Failed to save config: {e:?}");
                             }
+                            should_reposition = true;
                         }
                     });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.config.enable_reposition_key, "Reposition Hotkey:");
+                        let selected = match &self.config.reposition_key {
+                            InputBinding::Key(key) => key_to_string(key),
+                            binding => binding.to_string(),
+                        };
+                        ui.add_enabled_ui(self.config.enable_reposition_key, |ui| {
+                            ComboBox::from_id_salt("reposition_key_combo")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    for key in &self.available_keys {
+                                        ui.selectable_value(
+                                            &mut self.config.reposition_key,
+                                            InputBinding::Key(*key),
+                                            key_to_string(key),
+                                        );
+                                    }
+                                });
+                        });
+                        self.source_label(ui, "reposition_key");
+                    });
+                    ui.label("(Timers grow away from the anchor on the selected display)");
                 });
                 ui.add_space(10.0);
 
                 // Start Key/Button
                 ui.group(|ui| {
                     ui.push_id("start_input", |ui| {
-                        ui.label("Start Key/Button (first input in sequence)");
+                        ui.horizontal(|ui| {
+                            ui.label("Start Key/Button (first input in sequence)");
+                            self.source_label(ui, "start_key");
+                        });
                         ui.horizontal(|ui| {
                             ui.radio_value(
                                 &mut self.start_input_type,
@@ -289,41 +696,32 @@ impl ConfigState {
                         match self.start_input_type {
                             InputType::Keyboard => {
                                 ComboBox::from_id_salt("start_key_combo")
-                                    .selected_text(key_to_string(
-                                        &self.available_keys[self.start_key_selected],
-                                    ))
+                                    .selected_text(key_to_string(&self.start_key_selected))
                                     .show_ui(ui, |ui| {
-                                        for (i, key) in self.available_keys.iter().enumerate() {
+                                        for key in &self.available_keys {
                                             ui.selectable_value(
                                                 &mut self.start_key_selected,
-                                                i,
+                                                *key,
                                                 key_to_string(key),
                                             );
                                         }
                                     });
-                                self.config.start_key =
-                                    InputBinding::Key(self.available_keys[self.start_key_selected])
-                                        .to_string();
+                                self.config.start_key = InputBinding::Key(self.start_key_selected);
                             }
                             InputType::Mouse => {
                                 ComboBox::from_id_salt("start_button_combo")
-                                    .selected_text(button_to_string(
-                                        &self.available_buttons[self.start_button_selected],
-                                    ))
+                                    .selected_text(button_to_string(&self.start_button_selected))
                                     .show_ui(ui, |ui| {
-                                        for (i, button) in self.available_buttons.iter().enumerate()
-                                        {
+                                        for button in &self.available_buttons {
                                             ui.selectable_value(
                                                 &mut self.start_button_selected,
-                                                i,
+                                                *button,
                                                 button_to_string(button),
                                             );
                                         }
                                     });
-                                self.config.start_key = InputBinding::Mouse(
-                                    self.available_buttons[self.start_button_selected],
-                                )
-                                .to_string();
+                                self.config.start_key =
+                                    InputBinding::Mouse(self.start_button_selected);
                             }
                         }
                     });
@@ -333,7 +731,10 @@ impl ConfigState {
                 // Confirm Key/Button
                 ui.group(|ui| {
                     ui.push_id("confirm_input", |ui| {
-                        ui.label("Confirm Key/Button (second input in sequence)");
+                        ui.horizontal(|ui| {
+                            ui.label("Confirm Key/Button (second input in sequence)");
+                            self.source_label(ui, "confirm_key");
+                        });
                         ui.horizontal(|ui| {
                             ui.radio_value(
                                 &mut self.confirm_input_type,
@@ -350,42 +751,33 @@ impl ConfigState {
                         match self.confirm_input_type {
                             InputType::Keyboard => {
                                 ComboBox::from_id_salt("confirm_key_combo")
-                                    .selected_text(key_to_string(
-                                        &self.available_keys[self.confirm_key_selected],
-                                    ))
+                                    .selected_text(key_to_string(&self.confirm_key_selected))
                                     .show_ui(ui, |ui| {
-                                        for (i, key) in self.available_keys.iter().enumerate() {
+                                        for key in &self.available_keys {
                                             ui.selectable_value(
                                                 &mut self.confirm_key_selected,
-                                                i,
+                                                *key,
                                                 key_to_string(key),
                                             );
                                         }
                                     });
-                                self.config.confirm_key = InputBinding::Key(
-                                    self.available_keys[self.confirm_key_selected],
-                                )
-                                .to_string();
+                                self.config.confirm_key =
+                                    InputBinding::Key(self.confirm_key_selected);
                             }
                             InputType::Mouse => {
                                 ComboBox::from_id_salt("confirm_button_combo")
-                                    .selected_text(button_to_string(
-                                        &self.available_buttons[self.confirm_button_selected],
-                                    ))
+                                    .selected_text(button_to_string(&self.confirm_button_selected))
                                     .show_ui(ui, |ui| {
-                                        for (i, button) in self.available_buttons.iter().enumerate()
-                                        {
+                                        for button in &self.available_buttons {
                                             ui.selectable_value(
                                                 &mut self.confirm_button_selected,
-                                                i,
+                                                *button,
                                                 button_to_string(button),
                                             );
                                         }
                                     });
-                                self.config.confirm_key = InputBinding::Mouse(
-                                    self.available_buttons[self.confirm_button_selected],
-                                )
-                                .to_string();
+                                self.config.confirm_key =
+                                    InputBinding::Mouse(self.confirm_button_selected);
                             }
                         }
                     });
@@ -395,6 +787,7 @@ impl ConfigState {
                 // Cancelable Keys/Buttons
                 ui.group(|ui| {
                     ui.label("Cancelable Inputs (keys/buttons that reset the sequence)");
+                    self.source_label(ui, "cancelable_keys");
                     ui.label("Select multiple inputs:");
 
                     // Add padding on the right by constraining the width
@@ -403,16 +796,18 @@ impl ConfigState {
 
                     ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
                         ui.label("Keyboard Keys:");
-                        for (i, key) in self.available_keys.iter().enumerate() {
+                        for key in &self.available_keys {
                             ui.horizontal(|ui| {
-                                let mut is_selected = self.cancelable_keys_selected.contains(&i);
+                                let binding = InputBinding::Key(*key);
+                                let cancelable = &mut self.config.cancelable_keys;
+                                let mut is_selected = cancelable.contains(&binding);
                                 if ui.checkbox(&mut is_selected, key_to_string(key)).changed() {
                                     if is_selected {
-                                        if !self.cancelable_keys_selected.contains(&i) {
-                                            self.cancelable_keys_selected.push(i);
+                                        if !cancelable.contains(&binding) {
+                                            cancelable.push(binding);
                                         }
                                     } else {
-                                        self.cancelable_keys_selected.retain(|&x| x != i);
+                                        cancelable.retain(|b| *b != binding);
                                     }
                                 }
                                 ui.allocate_space(egui::vec2(ui.available_width(), 0.0));
@@ -424,97 +819,648 @@ impl ConfigState {
                         ui.add_space(10.0);
 
                         ui.label("Mouse Buttons:");
-                        for (i, button) in self.available_buttons.iter().enumerate() {
+                        for button in &self.available_buttons {
                             ui.horizontal(|ui| {
-                                let mut is_selected = self.cancelable_buttons_selected.contains(&i);
+                                let binding = InputBinding::Mouse(*button);
+                                let cancelable = &mut self.config.cancelable_keys;
+                                let mut is_selected = cancelable.contains(&binding);
                                 if ui
                                     .checkbox(&mut is_selected, button_to_string(button))
                                     .changed()
                                 {
                                     if is_selected {
-                                        if !self.cancelable_buttons_selected.contains(&i) {
-                                            self.cancelable_buttons_selected.push(i);
+                                        if !cancelable.contains(&binding) {
+                                            cancelable.push(binding);
                                         }
                                     } else {
-                                        self.cancelable_buttons_selected.retain(|&x| x != i);
+                                        cancelable.retain(|b| *b != binding);
                                     }
                                 }
                                 ui.allocate_space(egui::vec2(ui.available_width(), 0.0));
                             });
                         }
                     });
-
-                    // Update config with both keys and buttons
-                    let mut cancelable = Vec::new();
-                    for &i in &self.cancelable_keys_selected {
-                        cancelable.push(InputBinding::Key(self.available_keys[i]).to_string());
-                    }
-                    for &i in &self.cancelable_buttons_selected {
-                        cancelable.push(InputBinding::Mouse(self.available_buttons[i]).to_string());
-                    }
-                    self.config.cancelable_keys = cancelable;
                 });
                 ui.add_space(10.0);
 
                 // Timer Start Duration
                 ui.group(|ui| {
-                    ui.label("Timer Duration (seconds)");
-                    ui.add(Slider::new(&mut self.config.timer_start, 1.0..=99.99).text("seconds"));
+                    ui.horizontal(|ui| {
+                        ui.label("Timer Duration (seconds)");
+                        self.source_label(ui, "timer_start");
+                    });
+                    ui.add(
+                        Slider::new(&mut self.config.timer_start, 1.0..=600.0)
+                            .logarithmic(true)
+                            .text("seconds"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Format:");
+                        ComboBox::from_id_salt("time_format_combo")
+                            .selected_text(self.config.time_format.to_string())
+                            .show_ui(ui, |ui| {
+                                for format in TimeFormat::ALL {
+                                    ui.selectable_value(
+                                        &mut self.config.time_format,
+                                        format,
+                                        format.to_string(),
+                                    );
+                                }
+                            });
+                        self.source_label(ui, "time_format");
+                    });
+                    ui.horizontal(|ui| {
+                        for rounding in TimeRounding::ALL {
+                            ui.radio_value(
+                                &mut self.config.time_rounding,
+                                rounding,
+                                rounding.to_string(),
+                            );
+                        }
+                        self.source_label(ui, "time_rounding");
+                    });
                 });
                 ui.add_space(10.0);
 
                 // Max Timers
                 ui.group(|ui| {
-                    ui.label("Maximum Active Timers");
+                    ui.horizontal(|ui| {
+                        ui.label("Maximum Active Timers");
+                        self.source_label(ui, "max_timers");
+                    });
                     ui.add(Slider::new(&mut self.config.max_timers, 1..=5).text("timers"));
                 });
                 ui.add_space(10.0);
 
+                // Layout
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Timer Layout");
+                        self.source_label(ui, "layout");
+                    });
+                    ui.horizontal(|ui| {
+                        for layout in OverlayLayout::ALL {
+                            ui.radio_value(&mut self.config.layout, layout, layout.to_string());
+                        }
+                    });
+                    if self.config.layout == OverlayLayout::Grid {
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                Slider::new(&mut self.config.grid_columns, 1..=5).text("columns"),
+                            );
+                            self.source_label(ui, "grid_columns");
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            Slider::new(&mut self.config.timer_spacing, 0.0..=100.0)
+                                .text("spacing (px)"),
+                        );
+                        self.source_label(ui, "timer_spacing");
+                    });
+                });
+                ui.add_space(10.0);
+
                 // Subtext Options
                 ui.group(|ui| {
                     ui.label("Timer Display Options");
-                    ui.checkbox(&mut self.config.show_subtext, "Show Subtext Label");
+                    ui.horizontal(|ui| {
+                        ui.label("Style:");
+                        for style in TimerStyle::ALL {
+                            ui.radio_value(&mut self.config.timer_style, style, style.to_string());
+                        }
+                        self.source_label(ui, "timer_style");
+                    });
+                    ui.label("(The bar and ring show the time left and use the warning colors)");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.config.show_subtext, "Show Subtext Label");
+                        self.source_label(ui, "show_subtext");
+                    });
                     if self.config.show_subtext {
                         ui.horizontal(|ui| {
                             ui.label("Subtext:");
                             ui.text_edit_singleline(&mut self.config.subtext_string);
+                            self.source_label(ui, "subtext_string");
                         });
                     }
-                    ui.checkbox(&mut self.config.show_numbering, "Show Timer Numbers (1-5)");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.config.show_numbering, "Show Timer Numbers (1-5)");
+                        self.source_label(ui, "show_numbering");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Subtext Template:");
+                        let placeholders = SUBTEXT_PLACEHOLDERS
+                            .iter()
+                            .map(|(placeholder, meaning)| format!("{placeholder}: {meaning}"))
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        ui.text_edit_singleline(&mut self.config.subtext_template)
+                            .on_hover_text(placeholders);
+                        self.source_label(ui, "subtext_template");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Subtext Position:");
+                        for position in SubtextPosition::ALL {
+                            ui.radio_value(
+                                &mut self.config.subtext_position,
+                                position,
+                                position.to_string(),
+                            );
+                        }
+                        self.source_label(ui, "subtext_position");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Icon File:");
+                        ui.text_edit_singleline(&mut self.config.icon_path)
+                            .on_hover_text("Path to a PNG shown next to each timer, or empty");
+                        self.source_label(ui, "icon_path");
+                    });
+                    if !self.config.icon_path.is_empty()
+                        && !Path::new(&self.config.icon_path).is_file()
+                    {
+                        ui.colored_label(
+                            Color32::YELLOW,
+                            format!("Icon {:?} doesn't exist", self.config.icon_path),
+                        );
+                    }
                 });
                 ui.add_space(10.0);
 
                 // Timer Behavior Options
                 ui.group(|ui| {
                     ui.label("Timer Behavior");
-                    ui.checkbox(&mut self.config.add_new_on_left, "Add New Timers on Left");
+                    ui.horizontal(|ui| {
+                        ui.checkbox(
+                            &mut self.config.add_new_on_left,
+                            "Add New Timers First (left/top)",
+                        );
+                        self.source_label(ui, "add_new_on_left");
+                    });
                     if !self.config.add_new_on_left {
-                        ui.label("(New timers will be added on the right)");
+                        ui.label("(New timers will be added last)");
                     }
-                    ui.checkbox(
-                        &mut self.config.overwrite_oldest,
-                        "Overwrite Oldest Timer When Full",
-                    );
+                    ui.horizontal(|ui| {
+                        ui.checkbox(
+                            &mut self.config.overwrite_oldest,
+                            "Overwrite Oldest Timer When Full",
+                        );
+                        self.source_label(ui, "overwrite_oldest");
+                    });
                     if !self.config.overwrite_oldest {
                         ui.label("(Will wait for free slot when at max timers)");
                     }
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.config.stable_slots, "Keep Timers in Their Slots");
+                        self.source_label(ui, "stable_slots");
+                    });
+                    if self.config.stable_slots {
+                        ui.label(
+                            "(Timers keep their place and number, new ones fill the first gap)",
+                        );
+                    }
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            Slider::new(&mut self.config.expired_linger, 0.0..=10.0)
+                                .text("sec to keep expired timers"),
+                        );
+                        self.source_label(ui, "expired_linger");
+                    });
+                    if self.config.expired_linger > 0.0 {
+                        ui.horizontal(|ui| {
+                            ui.label("Expired Text:");
+                            ui.text_edit_singleline(&mut self.config.expired_text)
+                                .on_hover_text("e.g. DOWN, or empty to show the zeroed time");
+                            self.source_label(ui, "expired_text");
+                        });
+                    }
                 });
                 ui.add_space(10.0);
 
-                // Red Text Warning Options
+                // Warning Stages
                 ui.group(|ui| {
-                    ui.checkbox(&mut self.config.enable_red_text, "Enable Red Text Warning");
-                    if self.config.enable_red_text {
+                    ui.horizontal(|ui| {
+                        ui.label("Warning Stages");
+                        self.source_label(ui, "warning_stages");
+                    });
+                    let max_threshold = self.config.timer_start;
+                    let mut remove_stage = None;
+                    for (i, stage) in self.config.warning_stages.iter_mut().enumerate() {
                         ui.horizontal(|ui| {
-                            ui.label("Warning Threshold:");
+                            ui.label("Below");
                             ui.add(
-                                DragValue::new(&mut self.config.red_text_threshold)
+                                DragValue::new(&mut stage.threshold)
                                     .speed(0.1)
-                                    .range(0.1..=self.config.timer_start)
+                                    .range(0.1..=max_threshold)
                                     .suffix(" sec"),
                             );
+                            ui.color_edit_button_srgba(&mut stage.color);
+                            if ui.button("Remove").clicked() {
+                                remove_stage = Some(i);
+                            }
                         });
-                        ui.label("Text turns red when time remaining is below this threshold");
+                    }
+                    if let Some(i) = remove_stage {
+                        self.config.warning_stages.remove(i);
+                    }
+                    if ui.button("Add Stage").clicked() {
+                        // Start the new stage halfway to zero from the most urgent one
+                        let threshold = self
+                            .config
+                            .warning_stages
+                            .iter()
+                            .map(|stage| stage.threshold)
+                            .reduce(f32::min)
+                            .map_or(max_threshold / 2.0, |threshold| threshold / 2.0);
+                        self.config.warning_stages.push(WarningStage {
+                            threshold: threshold.max(0.1),
+                            color: Color32::RED,
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.checkbox(
+                            &mut self.config.blend_warning_stages,
+                            "Blend Colors Between Stages",
+                        );
+                        self.source_label(ui, "blend_warning_stages");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(
+                            &mut self.config.pulse_final_stage,
+                            "Pulse in the Final Stage",
+                        );
+                        self.source_label(ui, "pulse_final_stage");
+                    });
+                    ui.label("Timer text takes the color of the most urgent stage it has reached");
+                });
+                ui.add_space(10.0);
+
+                // Theme
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Theme");
+                        if ui.button("Reset Theme").clicked() {
+                            self.config.theme = Theme::default();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Timer Text:");
+                        ui.color_edit_button_srgba(&mut self.config.theme.text_color);
+                        self.source_label(ui, "theme.text_color");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Subtext:");
+                        ui.color_edit_button_srgba(&mut self.config.theme.subtext_color);
+                        self.source_label(ui, "theme.subtext_color");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Background:");
+                        // The alpha comes from the opacity slider
+                        let [r, g, b, _] =
+                            self.config.theme.background_color.to_srgba_unmultiplied();
+                        let mut rgb = [r, g, b];
+                        if ui.color_edit_button_srgb(&mut rgb).changed() {
+                            self.config.theme.background_color =
+                                Color32::from_rgb(rgb[0], rgb[1], rgb[2]);
+                        }
+                        self.source_label(ui, "theme.background_color");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            Slider::new(&mut self.config.theme.background_opacity, 0..=255)
+                                .text("background opacity"),
+                        );
+                        self.source_label(ui, "theme.background_opacity");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            Slider::new(&mut self.config.theme.timer_font_size, 12.0..=128.0)
+                                .text("timer font size"),
+                        );
+                        self.source_label(ui, "theme.timer_font_size");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            Slider::new(&mut self.config.theme.subtext_font_size, 6.0..=48.0)
+                                .text("subtext font size"),
+                        );
+                        self.source_label(ui, "theme.subtext_font_size");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            Slider::new(&mut self.config.theme.corner_radius, 0.0..=30.0)
+                                .text("corner radius"),
+                        );
+                        self.source_label(ui, "theme.corner_radius");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Font File:");
+                        ui.text_edit_singleline(&mut self.config.theme.font_path)
+                            .on_hover_text("Path to a TTF/OTF font, or empty for the default");
+                        if ui.button("Load").clicked() {
+                            self.font_warning = load_fonts(ctx, &self.config.theme.font_path);
+                        }
+                        self.source_label(ui, "theme.font_path");
+                    });
+                    if let Some(warning) = &self.font_warning {
+                        ui.colored_label(Color32::YELLOW, warning);
+                    }
+                });
+                ui.add_space(10.0);
+
+                // Audio Cues
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.config.audio.enabled, "Play Sounds");
+                        self.source_label(ui, "audio.enabled");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Output:");
+                        for output in AudioOutput::ALL {
+                            ui.radio_value(
+                                &mut self.config.audio.output,
+                                output,
+                                output.to_string(),
+                            );
+                        }
+                        self.source_label(ui, "audio.output");
+                    });
+                    if self.config.audio.output == AudioOutput::File {
+                        ui.horizontal(|ui| {
+                            ui.label("Log File:");
+                            ui.text_edit_singleline(&mut self.config.audio.output_file);
+                            self.source_label(ui, "audio.output_file");
+                        });
+                    }
+                    for (cue, label) in [
+                        (Cue::Start, "Start:"),
+                        (Cue::Warning, "Warning Stage:"),
+                        (Cue::Expiry, "Expiry:"),
+                        (Cue::Tick, "Countdown Tick:"),
+                    ] {
+                        ui.horizontal(|ui| {
+                            ui.label(label);
+                            let sound = cue.sound_cue_mut(&mut self.config.audio);
+                            ui.add(Slider::new(&mut sound.volume, 0.0..=1.0).text("volume"));
+                            ui.text_edit_singleline(&mut sound.sound_path)
+                                .on_hover_text(
+                                    "Path to a WAV file, or empty for the built-in tone",
+                                );
+                            if ui.button("Play").clicked() {
+                                // Always heard, even with sounds turned off
+                                let mut audio = self.config.audio.clone();
+                                audio.enabled = true;
+                                let cues = match &mut self.audio_test {
+                                    Some((built_from, cues)) if *built_from == audio => cues,
+                                    test => {
+                                        &mut test.insert((audio.clone(), AudioCues::new(&audio))).1
+                                    }
+                                };
+                                cues.play(cue);
+                            }
+                            self.source_label(ui, &format!("audio.{}.volume", cue.key()));
+                        });
+                    }
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            Slider::new(&mut self.config.audio.tick_seconds, 1.0..=10.0)
+                                .step_by(1.0)
+                                .text("tick seconds"),
+                        );
+                        self.source_label(ui, "audio.tick_seconds");
+                    });
+                    ui.label("A volume of 0 turns that sound off");
+                });
+                ui.add_space(10.0);
+
+                // Command Hooks
+                ui.group(|ui| {
+                    ui.label("Command Hooks");
+                    ui.horizontal(|ui| {
+                        ui.label("On Start:");
+                        ui.text_edit_singleline(&mut self.config.hooks.on_start)
+                            .on_hover_text(HOOK_HINT);
+                        self.source_label(ui, "hooks.on_start");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("On Expire:");
+                        ui.text_edit_singleline(&mut self.config.hooks.on_expire)
+                            .on_hover_text(HOOK_HINT);
+                        self.source_label(ui, "hooks.on_expire");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("On Cancel:");
+                        ui.text_edit_singleline(&mut self.config.hooks.on_cancel)
+                            .on_hover_text(HOOK_HINT);
+                        self.source_label(ui, "hooks.on_cancel");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            Slider::new(&mut self.config.hooks.timeout, 0.5..=60.0)
+                                .text("timeout (seconds)"),
+                        );
+                        self.source_label(ui, "hooks.timeout");
+                    });
+                    ui.label("Cancel runs when a timer is overwritten to make room for a new one");
+                });
+                ui.add_space(10.0);
+
+                // Session Log
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Session Log:");
+                        for format in SessionLogFormat::ALL {
+                            ui.radio_value(
+                                &mut self.config.session_log,
+                                format,
+                                format.to_string(),
+                            );
+                        }
+                        self.source_label(ui, "session_log");
+                    });
+                    match &self.log_dir {
+                        Ok(dir) => ui.weak(format!("A new log in {} each session", dir.display())),
+                        Err(e) => ui.weak(format!("Failed to get log directory: {e}")),
+                    };
+                });
+                ui.add_space(10.0);
+
+                // Session Statistics
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Session Statistics");
+                        let text = if self.session_stats.is_some() {
+                            "Refresh"
+                        } else {
+                            "Load"
+                        };
+                        if ui.button(text).clicked() {
+                            self.session_stats = Some(stats::load().unwrap_or_else(|e| {
+                                eprintln!("Failed to load session logs: {e:?}");
+                                Vec::new()
+                            }));
+                            self.selected_session = None;
+                        }
+                    });
+
+                    let Some(sessions) = &self.session_stats else {
+                        return;
+                    };
+                    if sessions.is_empty() {
+                        ui.label("(Sessions show up here once the session log is on)");
+                        return;
+                    }
+
+                    ScrollArea::vertical()
+                        .id_salt("sessions_scroll")
+                        .max_height(150.0)
+                        .show(ui, |ui| {
+                            egui::Grid::new("sessions_grid")
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    for header in [
+                                        "Session",
+                                        "Activations",
+                                        "Full",
+                                        "Dropped",
+                                        "Overwrote",
+                                        "Canceled",
+                                        "Average Gap",
+                                    ] {
+                                        ui.strong(header);
+                                    }
+                                    ui.end_row();
+
+                                    for (i, session) in sessions.iter().enumerate() {
+                                        let started = session.started.format("%Y-%m-%d %H:%M");
+                                        if ui
+                                            .selectable_label(
+                                                self.selected_session == Some(i),
+                                                started.to_string(),
+                                            )
+                                            .clicked()
+                                        {
+                                            self.selected_session = Some(i);
+                                        }
+                                        ui.label(session.activations.to_string());
+                                        ui.label(match session.full_ratio() {
+                                            Some(ratio) => format!(
+                                                "{} ({:.0}%)",
+                                                session.full(),
+                                                ratio * 100.0
+                                            ),
+                                            None => "-".to_string(),
+                                        });
+                                        ui.label(session.dropped.to_string());
+                                        ui.label(session.evictions.to_string());
+                                        ui.label(session.cancellations.to_string());
+                                        ui.label(match session.average_gap {
+                                            Some(gap) => format!("{gap:.1}s"),
+                                            None => "-".to_string(),
+                                        });
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                    ui.label("Full counts activations that found every slot taken");
+
+                    if let Some(session) = self.selected_session.and_then(|i| sessions.get(i)) {
+                        ui.separator();
+                        ui.label(format!(
+                            "Timeline of {} (overwritten timers in orange, dropped \
+                             activations in red)",
+                            session.started.format("%Y-%m-%d %H:%M")
+                        ));
+                        ScrollArea::horizontal()
+                            .id_salt("timeline_scroll")
+                            .show(ui, |ui| draw_timeline(ui, session));
+                    }
+                });
+                ui.add_space(10.0);
+
+                // Preview
+                ui.group(|ui| {
+                    ui.label("Preview");
+                    self.preview_remaining = self.preview_remaining.min(self.config.timer_start);
+                    ui.add(
+                        Slider::new(&mut self.preview_remaining, 0.0..=self.config.timer_start)
+                            .text("seconds left on the newest timer"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Test Fire:");
+                        if ui.button("Press Start").clicked() {
+                            self.simulate_inputs(&[self.config.start_key]);
+                        }
+                        if ui.button("Press Confirm").clicked() {
+                            self.simulate_inputs(&[self.config.confirm_key]);
+                        }
+                        if let Some(&cancel) = self.config.cancelable_keys.first()
+                            && ui.button("Press Cancel").clicked()
+                        {
+                            self.simulate_inputs(&[cancel]);
+                        }
+                        if ui.button("Start + Confirm").clicked() {
+                            self.simulate_inputs(&[self.config.start_key, self.config.confirm_key]);
+                        }
+                        if ui.button("Spawn Timer").clicked() {
+                            self.spawn_test_timer();
+                            self.test_status = format!("Spawned: {}", self.test_status);
+                        }
+                        if ui.button("Clear").clicked() {
+                            self.test_timers.clear();
+                            self.test_status.clear();
+                        }
+                    });
+                    if !self.test_status.is_empty() {
+                        ui.label(&self.test_status);
+                    }
+                    self.draw_preview(ctx, ui);
+                });
+                ui.add_space(10.0);
+
+                // Config Backups
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Config Backups");
+                        self.source_label(ui, "max_backups");
+                    });
+                    ui.add(Slider::new(&mut self.config.max_backups, 0..=50).text("kept"));
+
+                    if self.backups.is_empty() {
+                        ui.label("(A backup is kept every time a save changes the config file)");
+                    }
+
+                    ScrollArea::vertical()
+                        .id_salt("backups_scroll")
+                        .max_height(100.0)
+                        .show(ui, |ui| {
+                            for (i, backup) in self.backups.iter().enumerate() {
+                                let created = backup.created.format("%Y-%m-%d %H:%M:%S");
+                                if ui
+                                    .selectable_label(
+                                        self.selected_backup == Some(i),
+                                        created.to_string(),
+                                    )
+                                    .clicked()
+                                {
+                                    backup_selection = Some(i);
+                                }
+                            }
+                        });
+
+                    if let Some(i) = self.selected_backup {
+                        ui.separator();
+                        if self.backup_changes.is_empty() {
+                            ui.label("(Identical to the current config file)");
+                        } else {
+                            ui.label("Changes if restored (current -> backup):");
+                            for change in &self.backup_changes {
+                                ui.monospace(change.to_string());
+                            }
+                        }
+                        if ui.button("Restore This Backup").clicked() {
+                            restore_backup = Some(i);
+                        }
+                        ui.label("(Restoring discards unsaved changes)");
                     }
                 });
                 ui.add_space(20.0);
@@ -522,7 +1468,7 @@ impl ConfigState {
                 // Buttons
                 ui.horizontal(|ui| {
                     if ui.button("Save and Start").clicked() {
-                        if let Err(e) = self.config.save() {
+                        if let Err(e) = self.resolved.save(&self.config) {
                             eprintln!("Failed to save config: {e:?}");
                         }
                         should_start = true;
@@ -539,12 +1485,38 @@ impl ConfigState {
             });
         });
 
-        if should_reset {
+        if let Some(i) = backup_selection {
+            if self.selected_backup == Some(i) {
+                self.selected_backup = None;
+            } else {
+                self.selected_backup = Some(i);
+                self.backup_changes = backup::diff(&self.backups[i]).unwrap_or_else(|e| {
+                    eprintln!("Failed to compare config backup: {e:?}");
+                    Vec::new()
+                });
+            }
+            None
+        } else if let Some(i) = restore_backup {
+            match backup::restore(&self.backups[i], self.config.max_backups) {
+                Ok(()) => {
+                    let resolved = ResolvedConfig::load();
+                    *self = ConfigState::new(resolved.config.clone(), resolved);
+                }
+                Err(e) => eprintln!("Failed to restore config backup: {e:?}"),
+            }
+            None
+        } else if let Some(profile) = profile_change {
+            match self.resolved.with_profile(profile) {
+                Ok(resolved) => *self = ConfigState::new(resolved.config.clone(), resolved),
+                Err(e) => eprintln!("Failed to switch profile: {e:?}"),
+            }
+            None
+        } else if should_reset {
             // Reset to defaults
-            *self = ConfigState::new(Config::default());
+            *self = ConfigState::new(Config::default(), self.resolved.clone());
             None
-        } else if should_start {
-            Some(self.config.clone())
+        } else if should_start || should_reposition {
+            Some((self.config.clone(), should_reposition))
         } else {
             None
         }
@@ -558,39 +1530,50 @@ enum InputEvent {
     MousePress(Button),
 }
 
+impl InputEvent {
+    fn from_binding(binding: InputBinding) -> Self {
+        match binding {
+            InputBinding::Key(key) => InputEvent::KeyPress(key),
+            InputBinding::Mouse(button) => InputEvent::MousePress(button),
+        }
+    }
+
+    fn binding(self) -> InputBinding {
+        match self {
+            InputEvent::KeyPress(key) => InputBinding::Key(key),
+            InputEvent::MousePress(button) => InputBinding::Mouse(button),
+        }
+    }
+}
+
 enum Command {
     StartTimer,
+    CancelSequence,
+    ToggleReposition,
 }
 
 struct TimerState {
     config: Config,
+    resolved: ResolvedConfig,
     timers: Vec<Timer>,
     rx: Receiver<Command>,
+    // While repositioning the overlay takes mouse input and shows placeholder timers
+    repositioning: bool,
+    // Shown on the overlay until the given time if the custom font failed to load
+    font_warning: Option<(String, Instant)>,
+    // Size the overlay window was last fitted to
+    overlay_size: Vec2,
+    icon: Option<TextureHandle>,
+    audio: AudioCues,
+    // When the last frame was drawn, to find the points in the countdown passed since
+    last_update: Instant,
+    session_log: Option<SessionLog>,
 }
 
-struct Timer {
-    end_time: Instant,
-}
-
-impl Timer {
-    fn new(duration_ms: u64) -> Self {
-        Self {
-            end_time: Instant::now() + Duration::from_millis(duration_ms),
-        }
-    }
-
-    fn remaining_ms(&self) -> i64 {
-        let now = Instant::now();
-        if now >= self.end_time {
-            0
-        } else {
-            self.end_time.duration_since(now).as_millis() as i64
-        }
-    }
-
-    fn is_finished(&self) -> bool {
-        self.remaining_ms() <= 0
-    }
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SequenceEnd {
+    Completed,
+    Canceled,
 }
 
 struct SequenceDetector {
@@ -603,33 +1586,27 @@ struct SequenceDetector {
 
 impl SequenceDetector {
     fn new(config: &Config) -> Self {
-        let start_binding =
-            InputBinding::from_string(&config.start_key).unwrap_or(InputBinding::Key(Key::KeyE));
-        let confirm_binding = InputBinding::from_string(&config.confirm_key)
-            .unwrap_or(InputBinding::Mouse(Button::Right));
-
         let mut cancel_keys = Vec::new();
         let mut cancel_buttons = Vec::new();
 
-        for s in &config.cancelable_keys {
-            if let Some(binding) = InputBinding::from_string(s) {
-                match binding {
-                    InputBinding::Key(k) => cancel_keys.push(k),
-                    InputBinding::Mouse(b) => cancel_buttons.push(b),
-                }
+        for binding in &config.cancelable_keys {
+            match binding {
+                InputBinding::Key(k) => cancel_keys.push(*k),
+                InputBinding::Mouse(b) => cancel_buttons.push(*b),
             }
         }
 
         Self {
             waiting_for_confirm: false,
-            start_binding,
-            confirm_binding,
+            start_binding: config.start_key,
+            confirm_binding: config.confirm_key,
             cancel_keys,
             cancel_buttons,
         }
     }
 
-    fn on_input(&mut self, input: InputEvent) -> bool {
+    /// Feed the detector a press, returning how the sequence ended if it just did
+    fn on_input(&mut self, input: InputEvent) -> Option<SequenceEnd> {
         match input {
             InputEvent::KeyPress(key) => {
                 if !self.waiting_for_confirm {
@@ -644,11 +1621,12 @@ impl SequenceDetector {
                     .any(|k| key_to_string(k) == key_to_string(&key))
                 {
                     self.waiting_for_confirm = false;
+                    return Some(SequenceEnd::Canceled);
                 } else if let InputBinding::Key(confirm_key) = &self.confirm_binding
                     && key_to_string(&key) == key_to_string(confirm_key)
                 {
                     self.waiting_for_confirm = false;
-                    return true;
+                    return Some(SequenceEnd::Completed);
                 }
             }
             InputEvent::MousePress(button) => {
@@ -664,38 +1642,57 @@ impl SequenceDetector {
                     .any(|b| button_to_string(b) == button_to_string(&button))
                 {
                     self.waiting_for_confirm = false;
+                    return Some(SequenceEnd::Canceled);
                 } else if let InputBinding::Mouse(confirm_button) = &self.confirm_binding
                     && button_to_string(&button) == button_to_string(confirm_button)
                 {
                     self.waiting_for_confirm = false;
-                    return true;
+                    return Some(SequenceEnd::Completed);
                 }
             }
         }
-        false
+        None
     }
 }
 
 impl TimerState {
-    fn new(config: Config) -> Self {
+    fn new(config: Config, resolved: ResolvedConfig, repositioning: bool) -> Self {
         let (tx, rx) = mpsc::channel();
+        let audio = AudioCues::new(&config.audio);
+        let session_log = (config.session_log != SessionLogFormat::Off)
+            .then(|| SessionLog::create(config.session_log))
+            .and_then(|log| {
+                log.inspect_err(|e| eprintln!("Failed to start session log: {e:?}"))
+                    .ok()
+            });
         let config_clone = config.clone();
 
         std::thread::spawn(move || {
             let mut detector = SequenceDetector::new(&config_clone);
-
-            if let Err(error) = listen(move |event: Event| match event.event_type {
-                EventType::KeyPress(key) => {
-                    if detector.on_input(InputEvent::KeyPress(key)) {
-                        let _ = tx.send(Command::StartTimer);
-                    }
-                }
-                EventType::ButtonPress(button) => {
-                    if detector.on_input(InputEvent::MousePress(button)) {
-                        let _ = tx.send(Command::StartTimer);
+            let reposition_key = config_clone
+                .enable_reposition_key
+                .then_some(config_clone.reposition_key);
+
+            if let Err(error) = listen(move |event: Event| {
+                let input = match event.event_type {
+                    EventType::KeyPress(key) => InputEvent::KeyPress(key),
+                    EventType::ButtonPress(button) => InputEvent::MousePress(button),
+                    _ => return,
+                };
+
+                if reposition_key == Some(input.binding()) {
+                    let _ = tx.send(Command::ToggleReposition);
+                } else {
+                    match detector.on_input(input) {
+                        Some(SequenceEnd::Completed) => {
+                            let _ = tx.send(Command::StartTimer);
+                        }
+                        Some(SequenceEnd::Canceled) => {
+                            let _ = tx.send(Command::CancelSequence);
+                        }
+                        None => {}
                     }
                 }
-                _ => {}
             }) {
                 eprintln!("Error listening to events: {error:?}");
             }
@@ -703,153 +1700,270 @@ impl TimerState {
 
         Self {
             config,
+            resolved,
             timers: Vec::new(),
             rx,
+            repositioning,
+            font_warning: None,
+            overlay_size: Vec2::ZERO,
+            icon: None,
+            audio,
+            last_update: Instant::now(),
+            session_log,
         }
     }
 
-    fn format_time(ms: i64) -> String {
-        let seconds = ms / 1000;
-        let millis = (ms % 1000) / 10;
-        format!("{seconds:02}:{millis:02}")
+    fn renderer(&self) -> OverlayRenderer<'_> {
+        OverlayRenderer {
+            config: &self.config,
+            profile: self.resolved.profile.as_deref(),
+            icon: self.icon.as_ref(),
+        }
     }
 
-    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
-        ctx.send_viewport_cmd(ViewportCommand::MousePassthrough(true));
+    /// Get the outer position of the overlay window that puts it at its anchor on the
+    /// configured display
+    fn overlay_position(ctx: &Context, config: &Config, overlay_size: Vec2) -> Pos2 {
+        let native_pixels_per_point = ctx.native_pixels_per_point().unwrap_or(1.0);
+        let (monitor, scale_factor) = Self::overlay_monitor(ctx, config);
 
-        while let Ok(command) = self.rx.try_recv() {
-            match command {
-                Command::StartTimer => {
-                    let duration_ms = (self.config.timer_start * 1000.0) as u64;
+        let factor = Vec2::from(config.anchor.factor());
+        let offset = match config.position_unit {
+            PositionUnit::Pixels => Vec2::from(config.initial_pos) * scale_factor,
+            PositionUnit::Percent => Vec2::from(config.initial_pos) / 100.0 * monitor.size(),
+        };
 
-                    if self.timers.len() < self.config.max_timers {
-                        // We have space, add the timer
-                        if self.config.add_new_on_left {
-                            self.timers.insert(0, Timer::new(duration_ms));
-                        } else {
-                            self.timers.push(Timer::new(duration_ms));
-                        }
-                    } else if self.config.overwrite_oldest {
-                        // At capacity but configured to overwrite
-                        if self.config.add_new_on_left {
-                            self.timers.pop(); // Remove oldest (rightmost)
-                            self.timers.insert(0, Timer::new(duration_ms));
-                        } else {
-                            self.timers.remove(0); // Remove oldest (leftmost)
-                            self.timers.push(Timer::new(duration_ms));
-                        }
-                    }
-                    // else: at capacity and not overwriting, do nothing (wait for free slot)
-                }
+        // Work out the position in the display's physical pixels, then convert it to the
+        // points that the window is positioned in
+        let position =
+            monitor.min + monitor.size() * factor + offset - overlay_size * scale_factor * factor;
+        (position.to_vec2() / native_pixels_per_point).to_pos2()
+    }
+
+    /// Get the offset from the anchor that puts the overlay at `outer_position`, the
+    /// inverse of `overlay_position`
+    fn overlay_offset(
+        ctx: &Context,
+        config: &Config,
+        outer_position: Pos2,
+        overlay_size: Vec2,
+    ) -> (f32, f32) {
+        let native_pixels_per_point = ctx.native_pixels_per_point().unwrap_or(1.0);
+        let (monitor, scale_factor) = Self::overlay_monitor(ctx, config);
+
+        let factor = Vec2::from(config.anchor.factor());
+        let position = outer_position.to_vec2() * native_pixels_per_point;
+        let offset = position - monitor.min.to_vec2() - monitor.size() * factor
+            + overlay_size * scale_factor * factor;
+
+        let offset = match config.position_unit {
+            PositionUnit::Pixels => offset / scale_factor,
+            PositionUnit::Percent => offset / monitor.size() * 100.0,
+        };
+        (offset.x.round(), offset.y.round())
+    }
+
+    /// Get the physical pixel rect and scale factor of the display the overlay goes on
+    fn overlay_monitor(ctx: &Context, config: &Config) -> (Rect, f32) {
+        let displays = get_displays();
+        match find_display(config, &displays) {
+            Some(display) => (display_rect(display), display.scale_factor),
+            None => {
+                // Final fallback, the monitor the window is currently on
+                let native_pixels_per_point = ctx.native_pixels_per_point().unwrap_or(1.0);
+                let monitor_size = ctx.input(|i| i.viewport().monitor_size);
+                let monitor_size = monitor_size.unwrap_or(Vec2::ZERO) * native_pixels_per_point;
+                (
+                    Rect::from_min_size(Pos2::ZERO, monitor_size),
+                    native_pixels_per_point,
+                )
             }
         }
+    }
 
-        self.timers.retain(|timer| !timer.is_finished());
-
-        CentralPanel::default()
-            .frame(egui::Frame {
-                fill: Color32::TRANSPARENT,
-                inner_margin: Margin::same(0),
-                outer_margin: Margin::same(0),
-                ..Default::default()
-            })
-            .show(ctx, |ui| {
-                if !self.timers.is_empty() {
-                    ui.horizontal(|ui| {
-                        ui.spacing_mut().item_spacing.x = 30.0;
+    /// Start a timer for an activation, along with its sound and commands
+    fn start_timer(&mut self) {
+        let outcome = overlay::start_timer(&mut self.timers, &self.config);
+        match outcome {
+            StartOutcome::Overwrote { replaced, number } => {
+                self.log_event(SessionEvent::Eviction, Some((&replaced, number)));
+                self.run_hook(HookEvent::Cancel, &replaced, number);
+            }
+            StartOutcome::Dropped => self.log_event(SessionEvent::Dropped, None),
+            StartOutcome::Started => {}
+        }
 
-                        for (i, timer) in self.timers.iter().enumerate() {
-                            ui.vertical(|ui| {
-                                let remaining = timer.remaining_ms();
-                                let time_str = Self::format_time(remaining);
+        if !matches!(outcome, StartOutcome::Dropped) {
+            self.audio.play(Cue::Start);
+            let newest = if self.config.add_new_on_left {
+                0
+            } else {
+                self.timers.len() - 1
+            };
+            let timer = self.timers[newest];
+            let number = overlay::timer_number(&self.config, &self.timers, newest);
+            self.log_event(SessionEvent::Activation, Some((&timer, number)));
+            self.run_hook(HookEvent::Start, &timer, number);
+        }
+    }
 
-                                let text_color = if self.config.enable_red_text
-                                    && remaining <= (self.config.red_text_threshold * 1000.0) as i64
-                                {
-                                    Color32::RED
-                                } else {
-                                    Color32::WHITE
-                                };
+    fn log_event(&mut self, event: SessionEvent, timer: Option<(&Timer, usize)>) {
+        if let Some(log) = &mut self.session_log {
+            log.write(event, timer);
+        }
+    }
 
-                                let font_id = FontId::monospace(48.0);
-                                let galley = ui.fonts(|f| {
-                                    f.layout_no_wrap(time_str.clone(), font_id.clone(), text_color)
-                                });
-                                let text_size = galley.size();
+    fn run_hook(&self, event: HookEvent, timer: &Timer, number: usize) {
+        let details = TimerDetails {
+            event,
+            label: self.config.subtext_string.clone(),
+            duration: timer.duration_ms as f32 / 1000.0,
+            remaining: timer.remaining_ms() as f32 / 1000.0,
+            index: number,
+        };
+        hooks::run(&self.config.hooks, details);
+    }
 
-                                let (rect, _) = ui.allocate_exact_size(text_size, Sense::hover());
+    /// Resize the overlay window to fit the layout whenever it changes, e.g. once a custom
+    /// font has loaded, and move it so it stays at its anchor
+    fn fit_to_content(&mut self, ctx: &Context) {
+        let overlay_size = self.renderer().overlay_size(ctx);
+        if (overlay_size - self.overlay_size).abs().max_elem() < 0.5 {
+            return;
+        }
 
-                                ui.painter().rect_filled(
-                                    rect.expand(10.0),
-                                    5.0,
-                                    Color32::from_rgba_unmultiplied(0, 0, 0, 180),
-                                );
+        ctx.send_viewport_cmd(ViewportCommand::InnerSize(overlay_size));
+        // While repositioning the window is where it was dragged to, not at the config offset,
+        // but it still starts out there
+        let first_fit = self.overlay_size == Vec2::ZERO;
+        if first_fit || !self.repositioning {
+            ctx.send_viewport_cmd(ViewportCommand::OuterPosition(Self::overlay_position(
+                ctx,
+                &self.config,
+                overlay_size,
+            )));
+        }
+        self.overlay_size = overlay_size;
+    }
 
-                                ui.painter().galley(rect.left_top(), galley, text_color);
+    /// Enter or leave reposition mode, saving where the overlay was dragged to on leaving
+    fn set_repositioning(&mut self, ctx: &Context, repositioning: bool) {
+        if self.repositioning && !repositioning {
+            match ctx.input(|i| i.viewport().outer_rect) {
+                Some(outer_rect) => {
+                    self.config.initial_pos =
+                        Self::overlay_offset(ctx, &self.config, outer_rect.min, self.overlay_size);
+                    if let Err(e) = self.resolved.save(&self.config) {
+                        eprintln!("Failed to save config: {e:?}");
+                    }
+                }
+                None => eprintln!("Failed to get the overlay position"),
+            }
+        }
+        self.repositioning = repositioning;
+    }
 
-                                if self.config.show_subtext || self.config.show_numbering {
-                                    let smoke_number = if self.config.add_new_on_left {
-                                        self.timers.len() - i
-                                    } else {
-                                        i + 1
-                                    };
+    /// Show why the custom font isn't used along the top of the overlay
+    fn draw_font_warning(&self, ui: &mut Ui, warning: &str) {
+        let rect = ui.max_rect();
+        let galley = ui.fonts(|f| {
+            f.layout(
+                warning.to_string(),
+                FontId::proportional(self.config.theme.subtext_font_size),
+                Color32::YELLOW,
+                rect.width(),
+            )
+        });
 
-                                    let mut subtext_parts = Vec::new();
-                                    if self.config.show_subtext
-                                        && !self.config.subtext_string.is_empty()
-                                    {
-                                        subtext_parts.push(self.config.subtext_string.clone());
-                                    }
-                                    if self.config.show_numbering {
-                                        subtext_parts.push(smoke_number.to_string());
-                                    }
+        let text_rect = Rect::from_min_size(rect.min, galley.size());
+        ui.painter().rect_filled(
+            text_rect,
+            self.config.theme.corner_radius * 0.6,
+            self.config.theme.background(),
+        );
+        ui.painter().galley(text_rect.min, galley, Color32::YELLOW);
+    }
 
-                                    let subtext = subtext_parts.join(" ");
+    /// Outline the overlay and let it be dragged around, finishing on a double click
+    fn draw_reposition_controls(&mut self, ctx: &Context, ui: &mut Ui) {
+        let rect = ui.max_rect();
+        let response = ui.interact(rect, ui.id().with("reposition"), Sense::click_and_drag());
+
+        ui.painter().rect_stroke(
+            rect.shrink(1.0),
+            5.0,
+            Stroke::new(2.0, Color32::YELLOW),
+            StrokeKind::Inside,
+        );
+        ui.painter().text(
+            rect.center(),
+            Align2::CENTER_CENTER,
+            "Drag to move, double-click to finish",
+            FontId::proportional(self.config.theme.subtext_font_size),
+            Color32::YELLOW,
+        );
+
+        if response.drag_started() {
+            ctx.send_viewport_cmd(ViewportCommand::StartDrag);
+        }
+        if response.double_clicked() {
+            self.set_repositioning(ctx, false);
+        }
+    }
 
-                                    if !subtext.is_empty() {
-                                        let subtext_font_id = FontId::monospace(12.0);
-                                        let subtext_galley = ui.fonts(|f| {
-                                            f.layout_no_wrap(
-                                                subtext.clone(),
-                                                subtext_font_id.clone(),
-                                                Color32::WHITE,
-                                            )
-                                        });
-                                        let subtext_size = subtext_galley.size();
+    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
+        while let Ok(command) = self.rx.try_recv() {
+            match command {
+                Command::ToggleReposition => self.set_repositioning(ctx, !self.repositioning),
+                Command::StartTimer => self.start_timer(),
+                Command::CancelSequence => self.log_event(SessionEvent::Cancellation, None),
+            }
+        }
 
-                                        let timer_width = text_size.x;
-                                        let subtext_width = subtext_size.x;
-                                        let x_offset = (timer_width - subtext_width) / 2.0;
+        let now = Instant::now();
+        let elapsed = now - self.last_update;
+        self.last_update = now;
+        self.audio.on_frame(&self.config, &self.timers, elapsed);
+        for i in 0..self.timers.len() {
+            let timer = self.timers[i];
+            if timer.passed(0, elapsed) {
+                let number = overlay::timer_number(&self.config, &self.timers, i);
+                self.log_event(SessionEvent::Expiry, Some((&timer, number)));
+                self.run_hook(HookEvent::Expire, &timer, number);
+            }
+        }
+        overlay::remove_expired(&mut self.timers, &self.config);
 
-                                        let (subtext_rect, _) = ui.allocate_exact_size(
-                                            egui::vec2(timer_width, subtext_size.y),
-                                            Sense::hover(),
-                                        );
+        ctx.send_viewport_cmd(ViewportCommand::MousePassthrough(!self.repositioning));
+        self.fit_to_content(ctx);
 
-                                        let centered_subtext_rect = Rect::from_min_size(
-                                            egui::pos2(
-                                                subtext_rect.min.x + x_offset,
-                                                subtext_rect.min.y,
-                                            ),
-                                            subtext_size,
-                                        );
+        CentralPanel::default()
+            .frame(egui::Frame {
+                fill: Color32::TRANSPARENT,
+                inner_margin: Margin::same(0),
+                outer_margin: Margin::same(0),
+                ..Default::default()
+            })
+            .show(ctx, |ui| {
+                // Show a full overlay of placeholder timers while repositioning
+                if self.repositioning {
+                    let duration_ms = (self.config.timer_start * 1000.0) as u64;
+                    let placeholders: Vec<Timer> = (0..self.config.max_timers)
+                        .map(|slot| Timer::new(duration_ms, slot))
+                        .collect();
+                    self.renderer().draw(ui, &placeholders);
+                } else {
+                    self.renderer().draw(ui, &self.timers);
+                }
 
-                                        ui.painter().rect_filled(
-                                            centered_subtext_rect.expand(5.0),
-                                            3.0,
-                                            Color32::from_rgba_unmultiplied(0, 0, 0, 180),
-                                        );
+                if self.repositioning {
+                    self.draw_reposition_controls(ctx, ui);
+                }
 
-                                        ui.painter().galley(
-                                            centered_subtext_rect.left_top(),
-                                            subtext_galley,
-                                            Color32::WHITE,
-                                        );
-                                    }
-                                }
-                            });
-                        }
-                    });
+                if let Some((warning, until)) = &self.font_warning
+                    && Instant::now() < *until
+                {
+                    self.draw_font_warning(ui, warning);
                 }
             });
 
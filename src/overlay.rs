@@ -0,0 +1,815 @@
+use crate::config::{Config, OverlayLayout, SubtextPosition, TimerStyle};
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Local, TimeDelta};
+use egui::{
+    Color32, ColorImage, Context, FontData, FontDefinitions, FontFamily, FontId, Pos2, Rect,
+    Stroke, TextureHandle, TextureOptions, Ui, UiBuilder, Vec2,
+};
+use std::f32::consts::TAU;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How far the timer backgrounds extend past their text
+const BACKGROUND_PADDING: f32 = 10.0;
+// Thickness of the progress bar and ring, and their gap from the text
+const PROGRESS_WIDTH: f32 = 6.0;
+const PROGRESS_GAP: f32 = 4.0;
+// Space between a timer's icon and its countdown
+const ICON_GAP: f32 = 8.0;
+// Space between a timer and subtext drawn beside it, clear of both their backgrounds
+const SUBTEXT_GAP: f32 = 20.0;
+// How far the subtext background extends past its text
+const SUBTEXT_PADDING: f32 = 5.0;
+// Length of one fade out and back in of the final warning stage pulse
+const PULSE_PERIOD_MS: i64 = 500;
+// Opacity an expired timer starts fading out from while it lingers
+const EXPIRED_OPACITY: f32 = 0.5;
+// Font family for the timers and subtext, so a custom font only applies to the overlay
+const OVERLAY_FONT_FAMILY: &str = "overlay";
+const CUSTOM_FONT_NAME: &str = "custom";
+
+/// Set up the overlay font family, preferring the font at `font_path` if one is set.
+/// Returns a warning if that font couldn't be loaded and the default is used instead
+pub fn load_fonts(ctx: &Context, font_path: &str) -> Option<String> {
+    let mut fonts = FontDefinitions::default();
+    let mut family = fonts.families[&FontFamily::Monospace].clone();
+    let mut warning = None;
+
+    if !font_path.is_empty() {
+        match read_font(font_path) {
+            Ok(data) => {
+                fonts.font_data.insert(
+                    CUSTOM_FONT_NAME.to_string(),
+                    Arc::new(FontData::from_owned(data)),
+                );
+                family.insert(0, CUSTOM_FONT_NAME.to_string());
+            }
+            Err(e) => {
+                eprintln!("Failed to load font: {e:?}");
+                warning = Some(format!("{e}, using the default font"));
+            }
+        }
+    }
+
+    fonts
+        .families
+        .insert(FontFamily::Name(OVERLAY_FONT_FAMILY.into()), family);
+    ctx.set_fonts(fonts);
+    warning
+}
+
+/// Read a font file, making sure it parses since egui panics on invalid fonts
+fn read_font(font_path: &str) -> Result<Vec<u8>> {
+    let data =
+        fs::read(font_path).with_context(|| format!("Failed to read font file: {font_path:?}"))?;
+    ab_glyph::FontRef::try_from_slice(&data)
+        .with_context(|| format!("Failed to parse font file: {font_path:?}"))?;
+    Ok(data)
+}
+
+/// Load the PNG at `path` into a texture for drawing next to the timers
+pub fn load_timer_icon(ctx: &Context, path: &str) -> Result<TextureHandle> {
+    let image = image::open(path)
+        .with_context(|| format!("Failed to load timer icon: {path:?}"))?
+        .into_rgba8();
+    let size = [image.width() as usize, image.height() as usize];
+    let image = ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+
+    Ok(ctx.load_texture("timer_icon", image, TextureOptions::LINEAR))
+}
+
+/// Replace each `{key}` in `template` with its value from `lookup`, keeping unknown ones
+fn fill_template(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut filled = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest
+            .find('}')
+            .and_then(|end| Some((end, lookup(&rest[1..end])?)));
+        match value {
+            Some((end, value)) => {
+                filled.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+fn overlay_font(size: f32) -> FontId {
+    FontId::new(size, FontFamily::Name(OVERLAY_FONT_FAMILY.into()))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Timer {
+    pub end_time: Instant,
+    // Wall-clock time the timer started, for logs
+    pub started_at: DateTime<Local>,
+    pub duration_ms: u64,
+    // Layout slot the timer is drawn in when slots are stable
+    pub slot: usize,
+}
+
+impl Timer {
+    pub fn new(duration_ms: u64, slot: usize) -> Self {
+        Self {
+            end_time: Instant::now() + Duration::from_millis(duration_ms),
+            started_at: Local::now(),
+            duration_ms,
+            slot,
+        }
+    }
+
+    /// Create a timer that has `remaining_ms` of its `duration_ms` left, for previews. A
+    /// negative `remaining_ms` makes a timer that ran out that long ago.
+    pub fn with_remaining(duration_ms: u64, remaining_ms: i64, slot: usize) -> Self {
+        let now = Instant::now();
+        let offset = Duration::from_millis(remaining_ms.unsigned_abs());
+        let end_time = if remaining_ms >= 0 {
+            now + offset
+        } else {
+            now.checked_sub(offset).unwrap_or(now)
+        };
+        let elapsed_ms = duration_ms as i64 - remaining_ms;
+        Self {
+            end_time,
+            started_at: Local::now() - TimeDelta::milliseconds(elapsed_ms),
+            duration_ms,
+            slot,
+        }
+    }
+
+    pub fn remaining_ms(&self) -> i64 {
+        let now = Instant::now();
+        if now >= self.end_time {
+            0
+        } else {
+            self.end_time.duration_since(now).as_millis() as i64
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.remaining_ms() <= 0
+    }
+
+    /// How long ago the timer ran out, or 0 if it's still running
+    pub fn overdue_ms(&self) -> i64 {
+        Instant::now()
+            .checked_duration_since(self.end_time)
+            .map_or(0, |overdue| overdue.as_millis() as i64)
+    }
+
+    /// Whether the countdown passed `ms` remaining in the last `elapsed`
+    pub fn passed(&self, ms: i64, elapsed: Duration) -> bool {
+        // Goes negative once the timer has run out, so expiry is only passed once
+        let now = self.remaining_ms() - self.overdue_ms();
+        // A timer that just started hasn't passed anything above its duration
+        let before = (now + elapsed.as_millis() as i64).min(self.duration_ms as i64);
+        before > ms && now <= ms
+    }
+}
+
+/// What happened to a timer activation
+#[derive(Clone, Copy, Debug)]
+pub enum StartOutcome {
+    Started,
+    // The oldest running timer was replaced to make room, `number` is the one it showed
+    Overwrote { replaced: Timer, number: usize },
+    // Every slot was taken and overwriting is off
+    Dropped,
+}
+
+/// Start a new timer in `timers`, making room for it the way the config asks
+pub fn start_timer(timers: &mut Vec<Timer>, config: &Config) -> StartOutcome {
+    let duration_ms = (config.timer_start * 1000.0) as u64;
+
+    let running = timers.iter().filter(|t| !t.is_finished()).count();
+    if running >= config.max_timers && !config.overwrite_oldest {
+        // At capacity and not overwriting, wait for a free slot
+        return StartOutcome::Dropped;
+    }
+
+    let mut outcome = StartOutcome::Started;
+    if timers.len() >= config.max_timers {
+        // Expired timers that are still lingering make way first, longest expired
+        // first, then the oldest running timer
+        let longest_expired = (0..timers.len())
+            .filter(|&i| timers[i].is_finished())
+            .max_by_key(|&i| timers[i].overdue_ms());
+        let oldest = (0..timers.len()).min_by_key(|&i| timers[i].end_time);
+        if let Some(i) = longest_expired.or(oldest) {
+            if !timers[i].is_finished() {
+                outcome = StartOutcome::Overwrote {
+                    replaced: timers[i],
+                    number: timer_number(config, timers, i),
+                };
+            }
+            timers.remove(i);
+        }
+    }
+
+    let slot = (0..config.max_timers)
+        .find(|&slot| timers.iter().all(|timer| timer.slot != slot))
+        .unwrap_or(0);
+    let timer = Timer::new(duration_ms, slot);
+    if config.add_new_on_left {
+        timers.insert(0, timer);
+    } else {
+        timers.push(timer);
+    }
+    outcome
+}
+
+/// Get the number shown with the timer at `index`
+pub fn timer_number(config: &Config, timers: &[Timer], index: usize) -> usize {
+    if config.stable_slots {
+        timers[index].slot + 1
+    } else if config.add_new_on_left {
+        timers.len() - index
+    } else {
+        index + 1
+    }
+}
+
+/// Drop expired timers once they've lingered for as long as the config asks
+pub fn remove_expired(timers: &mut Vec<Timer>, config: &Config) {
+    let linger_ms = (config.expired_linger * 1000.0) as i64;
+    timers
+        .retain(|timer| !timer.is_finished() || (linger_ms > 0 && timer.overdue_ms() < linger_ms));
+}
+
+/// Draws timers the way the overlay shows them, for the overlay itself and for previews
+pub struct OverlayRenderer<'a> {
+    pub config: &'a Config,
+    pub profile: Option<&'a str>,
+    pub icon: Option<&'a TextureHandle>,
+}
+
+impl OverlayRenderer<'_> {
+    fn format_time(config: &Config, ms: i64) -> String {
+        let unit_ms = config.time_format.unit_ms(ms, config.time_rounding);
+        config
+            .time_format
+            .format(config.time_rounding.round(ms, unit_ms))
+    }
+
+    /// Measure the space a single timer takes up, including its subtext
+    fn cell_size(&self, ctx: &Context) -> Vec2 {
+        let config = self.config;
+        // The text is widest either at the start or just before its format changes
+        let duration_ms = (config.timer_start * 1000.0) as i64;
+        let text_size = [0, duration_ms, duration_ms.min(59_999)]
+            .into_iter()
+            .map(|ms| Self::format_time(config, ms))
+            .chain((config.expired_linger > 0.0).then(|| config.expired_text.clone()))
+            .map(|text| {
+                ctx.fonts(|f| {
+                    f.layout_no_wrap(
+                        text,
+                        overlay_font(config.theme.timer_font_size),
+                        config.theme.text_color,
+                    )
+                    .size()
+                })
+            })
+            .fold(Vec2::ZERO, Vec2::max);
+        let timer_size = Self::timer_size(config, text_size);
+
+        // Make room for the longest subtext a timer can have
+        let subtext = self.subtext(
+            config.max_timers,
+            duration_ms,
+            Local::now(),
+            config.max_timers,
+        );
+        if subtext.is_empty() {
+            return timer_size;
+        }
+
+        let subtext_size = ctx.fonts(|f| {
+            f.layout_no_wrap(
+                subtext,
+                overlay_font(config.theme.subtext_font_size),
+                config.theme.subtext_color,
+            )
+            .size()
+        });
+        match config.subtext_position {
+            SubtextPosition::Above | SubtextPosition::Below => egui::vec2(
+                timer_size.x,
+                timer_size.y + ctx.style().spacing.item_spacing.y + subtext_size.y,
+            ),
+            SubtextPosition::Beside => egui::vec2(
+                timer_size.x + SUBTEXT_GAP + subtext_size.x,
+                timer_size.y.max(subtext_size.y),
+            ),
+        }
+    }
+
+    /// Fill in the subtext template for a timer
+    fn subtext(
+        &self,
+        smoke_number: usize,
+        elapsed_ms: i64,
+        ends_at: DateTime<Local>,
+        charges_left: usize,
+    ) -> String {
+        let filled = fill_template(&self.config.subtext_template, |key| match key {
+            "label" if self.config.show_subtext => Some(self.config.subtext_string.clone()),
+            "n" if self.config.show_numbering => Some(smoke_number.to_string()),
+            "label" | "n" => Some(String::new()),
+            "elapsed" => Some(format!("{:.1}", elapsed_ms as f32 / 1000.0)),
+            "ends_at" => Some(ends_at.format("%H:%M:%S").to_string()),
+            "profile" => Some(self.profile.unwrap_or_default().to_string()),
+            "charges_left" => Some(charges_left.to_string()),
+            _ => None,
+        });
+
+        // Collapse the gaps left by placeholders that came out empty
+        filled.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Get the offset of the timer in slot `index` from the top-left of the overlay
+    fn slot_offset(config: &Config, index: usize, cell: Vec2) -> Vec2 {
+        let columns = config.grid_columns.max(1);
+        let (column, row) = match config.layout {
+            OverlayLayout::Row => (index, 0),
+            OverlayLayout::Column => (0, index),
+            OverlayLayout::Grid => (index % columns, index / columns),
+        };
+
+        egui::vec2(
+            column as f32 * (cell.x + config.timer_spacing),
+            row as f32 * (cell.y + config.timer_spacing),
+        )
+    }
+
+    /// Get the size of the area that `count` timers take up in the configured layout
+    fn layout_size(config: &Config, count: usize, cell: Vec2) -> Vec2 {
+        (0..count)
+            .map(|i| Self::slot_offset(config, i, cell) + cell)
+            .fold(Vec2::ZERO, Vec2::max)
+    }
+
+    /// Get the overlay window size needed to fit the maximum number of timers
+    pub fn overlay_size(&self, ctx: &Context) -> Vec2 {
+        let config = self.config;
+        let cell = self.cell_size(ctx);
+        Self::layout_size(config, config.max_timers, cell) + Vec2::splat(BACKGROUND_PADDING)
+    }
+
+    /// Draw `timers` into the overlay area of `ui`, aligned to the anchor so they grow away
+    /// from it
+    pub fn draw(&self, ui: &mut Ui, timers: &[Timer]) {
+        let ctx = ui.ctx().clone();
+        let cell = self.cell_size(&ctx);
+        let charges_left = self
+            .config
+            .max_timers
+            .saturating_sub(timers.iter().filter(|t| !t.is_finished()).count());
+
+        let content_size = self.overlay_size(&ctx) - Vec2::splat(BACKGROUND_PADDING);
+        // Stable slots stay put, so they're aligned as if every slot was in use
+        let used_count = if self.config.stable_slots {
+            self.config.max_timers
+        } else {
+            timers.len()
+        };
+        let used_size = Self::layout_size(self.config, used_count, cell);
+        let origin = ui.max_rect().min
+            + (content_size - used_size).max(Vec2::ZERO) * Vec2::from(self.config.anchor.factor());
+
+        for (i, timer) in timers.iter().enumerate() {
+            let slot = if self.config.stable_slots {
+                timer.slot
+            } else {
+                i
+            };
+            let smoke_number = timer_number(self.config, timers, i);
+
+            let offset = Self::slot_offset(self.config, slot, cell);
+            let rect = Rect::from_min_size(origin + offset, cell);
+
+            ui.scope_builder(UiBuilder::new().max_rect(rect), |ui| {
+                self.draw_timer(ui, timer, smoke_number, charges_left);
+            });
+        }
+    }
+
+    /// Get the timer text color for the warning stage that `remaining` ms falls in
+    fn text_color(config: &Config, remaining: i64) -> Color32 {
+        let remaining = remaining as f32 / 1000.0;
+        let mut stages = config.warning_stages.clone();
+        stages.sort_by(|a, b| b.threshold.total_cmp(&a.threshold));
+
+        // The most urgent stage that has been reached
+        let Some(current) = stages.iter().rposition(|s| remaining <= s.threshold) else {
+            return config.theme.text_color;
+        };
+        let stage = stages[current];
+
+        match stages.get(current + 1) {
+            Some(next) if config.blend_warning_stages && stage.threshold > next.threshold => {
+                let t = (stage.threshold - remaining) / (stage.threshold - next.threshold);
+                stage.color.lerp_to_gamma(next.color, t.clamp(0.0, 1.0))
+            }
+            _ => stage.color,
+        }
+    }
+
+    /// Get the size of a timer's countdown and its icon
+    fn timer_size(config: &Config, text_size: Vec2) -> Vec2 {
+        let countdown_size = Self::countdown_size(config, text_size);
+        if config.icon_path.is_empty() {
+            countdown_size
+        } else {
+            countdown_size + egui::vec2(text_size.y + ICON_GAP, 0.0)
+        }
+    }
+
+    /// Get the size of a timer's countdown text together with its progress bar or ring
+    fn countdown_size(config: &Config, text_size: Vec2) -> Vec2 {
+        match config.timer_style {
+            TimerStyle::Text => text_size,
+            TimerStyle::Bar => text_size + egui::vec2(0.0, PROGRESS_GAP + PROGRESS_WIDTH),
+            TimerStyle::Ring => {
+                Vec2::splat(text_size.length() + 2.0 * (PROGRESS_GAP + PROGRESS_WIDTH))
+            }
+        }
+    }
+
+    /// Check whether `remaining` ms is within the most urgent warning stage
+    fn in_final_stage(config: &Config, remaining: i64) -> bool {
+        config
+            .warning_stages
+            .iter()
+            .map(|stage| stage.threshold)
+            .reduce(f32::min)
+            .is_some_and(|threshold| remaining as f32 / 1000.0 <= threshold)
+    }
+
+    fn draw_timer(&self, ui: &mut Ui, timer: &Timer, smoke_number: usize, charges_left: usize) {
+        let theme = &self.config.theme;
+        let remaining = timer.remaining_ms();
+        let duration = timer.duration_ms as i64;
+
+        let time_str = if timer.is_finished() && !self.config.expired_text.is_empty() {
+            self.config.expired_text.clone()
+        } else {
+            Self::format_time(self.config, remaining)
+        };
+
+        if timer.is_finished() {
+            // Fade out over the time an expired timer lingers
+            let linger_ms = (self.config.expired_linger * 1000.0).max(1.0);
+            let faded = 1.0 - timer.overdue_ms() as f32 / linger_ms;
+            ui.multiply_opacity(EXPIRED_OPACITY * faded.clamp(0.0, 1.0));
+        }
+
+        let mut text_color = Self::text_color(self.config, remaining);
+        if self.config.pulse_final_stage
+            && !timer.is_finished()
+            && Self::in_final_stage(self.config, remaining)
+        {
+            // Fade out and back in, in step with the countdown
+            let phase = (remaining % PULSE_PERIOD_MS) as f32 / PULSE_PERIOD_MS as f32;
+            text_color = text_color.gamma_multiply(0.6 + 0.4 * (TAU * phase).cos());
+        }
+        let track_color = text_color.gamma_multiply(0.25);
+        let progress = if duration > 0 {
+            (remaining as f32 / duration as f32).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let font_id = overlay_font(theme.timer_font_size);
+        let galley = ui.fonts(|f| f.layout_no_wrap(time_str.clone(), font_id.clone(), text_color));
+        let text_size = galley.size();
+
+        let subtext = self.subtext(
+            smoke_number,
+            duration - remaining,
+            Local::now() + TimeDelta::milliseconds(remaining),
+            charges_left,
+        );
+        let subtext_galley = (!subtext.is_empty()).then(|| {
+            ui.fonts(|f| {
+                f.layout_no_wrap(
+                    subtext,
+                    overlay_font(theme.subtext_font_size),
+                    theme.subtext_color,
+                )
+            })
+        });
+
+        let cell = ui.max_rect();
+        let spacing = ui.spacing().item_spacing.y;
+        let timer_min = match &subtext_galley {
+            Some(galley) if self.config.subtext_position == SubtextPosition::Above => {
+                cell.min + egui::vec2(0.0, galley.size().y + spacing)
+            }
+            _ => cell.min,
+        };
+        let timer_rect = Rect::from_min_size(timer_min, Self::timer_size(self.config, text_size));
+        // The countdown sits on the right, after the icon if there is one
+        let countdown_size = Self::countdown_size(self.config, text_size);
+        let rect = Rect::from_min_size(
+            egui::pos2(timer_rect.right() - countdown_size.x, timer_rect.top()),
+            countdown_size,
+        );
+
+        let icon_rect = (!self.config.icon_path.is_empty()).then(|| {
+            Rect::from_center_size(
+                egui::pos2(timer_rect.left() + text_size.y / 2.0, rect.center().y),
+                Vec2::splat(text_size.y),
+            )
+        });
+
+        // The text and bar share a background with the icon, the ring has its own round one
+        match self.config.timer_style {
+            TimerStyle::Text | TimerStyle::Bar => {
+                ui.painter().rect_filled(
+                    icon_rect
+                        .map_or(rect, |icon_rect| icon_rect.union(rect))
+                        .expand(BACKGROUND_PADDING),
+                    theme.corner_radius,
+                    theme.background(),
+                );
+            }
+            TimerStyle::Ring => {
+                ui.painter().circle_filled(
+                    rect.center(),
+                    rect.width() / 2.0 + BACKGROUND_PADDING,
+                    theme.background(),
+                );
+                if let Some(icon_rect) = icon_rect {
+                    ui.painter().rect_filled(
+                        icon_rect.expand(BACKGROUND_PADDING),
+                        theme.corner_radius,
+                        theme.background(),
+                    );
+                }
+            }
+        }
+
+        if let (Some(icon), Some(icon_rect)) = (&self.icon, icon_rect) {
+            // Only tint the icon once a warning stage has been reached
+            let tint = if text_color == theme.text_color {
+                Color32::WHITE
+            } else {
+                text_color
+            };
+            ui.painter().image(
+                icon.id(),
+                icon_rect,
+                Rect::from_min_max(Pos2::ZERO, egui::pos2(1.0, 1.0)),
+                tint,
+            );
+        }
+
+        // Center the text, its width changes with the time format
+        let text_pos = egui::pos2(rect.center().x - text_size.x / 2.0, rect.top());
+
+        match self.config.timer_style {
+            TimerStyle::Text => {
+                ui.painter().galley(text_pos, galley, text_color);
+            }
+            TimerStyle::Bar => {
+                ui.painter().galley(text_pos, galley, text_color);
+
+                let track = Rect::from_min_max(
+                    egui::pos2(rect.left(), rect.bottom() - PROGRESS_WIDTH),
+                    rect.max,
+                );
+                let fill = Rect::from_min_size(
+                    track.min,
+                    egui::vec2(track.width() * progress, track.height()),
+                );
+                let rounding = PROGRESS_WIDTH / 2.0;
+                ui.painter().rect_filled(track, rounding, track_color);
+                ui.painter().rect_filled(fill, rounding, text_color);
+            }
+            TimerStyle::Ring => {
+                let center = rect.center();
+                let radius = (rect.width() - PROGRESS_WIDTH) / 2.0;
+                ui.painter()
+                    .galley(center - text_size / 2.0, galley, text_color);
+
+                ui.painter().circle_stroke(
+                    center,
+                    radius,
+                    Stroke::new(PROGRESS_WIDTH, track_color),
+                );
+                // Sweep clockwise from the top, emptying as time runs out
+                if progress > 0.0 {
+                    let segments = ((64.0 * progress).ceil() as usize).max(1);
+                    let points: Vec<Pos2> = (0..=segments)
+                        .map(|i| {
+                            let angle = TAU * progress * i as f32 / segments as f32 - TAU / 4.0;
+                            center + radius * Vec2::angled(angle)
+                        })
+                        .collect();
+                    ui.painter()
+                        .line(points, Stroke::new(PROGRESS_WIDTH, text_color));
+                }
+            }
+        }
+
+        if let Some(subtext_galley) = subtext_galley {
+            let subtext_size = subtext_galley.size();
+            let subtext_min = match self.config.subtext_position {
+                SubtextPosition::Above => {
+                    egui::pos2(timer_rect.center().x - subtext_size.x / 2.0, cell.top())
+                }
+                SubtextPosition::Below => egui::pos2(
+                    timer_rect.center().x - subtext_size.x / 2.0,
+                    timer_rect.bottom() + spacing,
+                ),
+                SubtextPosition::Beside => egui::pos2(
+                    timer_rect.right() + SUBTEXT_GAP,
+                    timer_rect.center().y - subtext_size.y / 2.0,
+                ),
+            };
+            let subtext_rect = Rect::from_min_size(subtext_min, subtext_size);
+
+            ui.painter().rect_filled(
+                subtext_rect.expand(SUBTEXT_PADDING),
+                // Keep the smaller subtext background in proportion to the timer's
+                theme.corner_radius * 0.6,
+                theme.background(),
+            );
+
+            ui.painter()
+                .galley(subtext_rect.left_top(), subtext_galley, theme.subtext_color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn passed_points_crossed_this_frame() {
+        let timer = Timer::with_remaining(10_000, 2_950, 0);
+        assert!(timer.passed(3_000, FRAME));
+        assert!(!timer.passed(3_000, Duration::from_millis(10)));
+        assert!(!timer.passed(2_000, FRAME));
+        assert!(!timer.passed(4_000, FRAME));
+    }
+
+    #[test]
+    fn new_timer_has_not_passed_its_duration() {
+        let timer = Timer::new(10_000, 0);
+        assert!(!timer.passed(10_000, FRAME));
+        assert!(!timer.passed(9_950, FRAME));
+    }
+
+    #[test]
+    fn expiry_is_passed_once() {
+        assert!(Timer::with_remaining(10_000, -20, 0).passed(0, FRAME));
+        assert!(!Timer::with_remaining(10_000, -500, 0).passed(0, FRAME));
+    }
+
+    fn slot_config(overwrite_oldest: bool) -> Config {
+        Config {
+            max_timers: 3,
+            add_new_on_left: false,
+            overwrite_oldest,
+            stable_slots: true,
+            ..Config::default()
+        }
+    }
+
+    fn slots(timers: &[Timer]) -> Vec<usize> {
+        timers.iter().map(|timer| timer.slot).collect()
+    }
+
+    #[test]
+    fn new_timers_take_the_first_free_slot() {
+        let config = slot_config(false);
+        let mut timers = Vec::new();
+        for _ in 0..3 {
+            assert!(matches!(
+                start_timer(&mut timers, &config),
+                StartOutcome::Started
+            ));
+        }
+        assert_eq!(slots(&timers), [0, 1, 2]);
+
+        timers.remove(1);
+        start_timer(&mut timers, &config);
+        assert_eq!(slots(&timers), [0, 2, 1]);
+        assert_eq!(timer_number(&config, &timers, 2), 2);
+    }
+
+    #[test]
+    fn full_overlay_drops_without_overwriting() {
+        let config = slot_config(false);
+        let mut timers: Vec<Timer> = (0..3)
+            .map(|slot| Timer::with_remaining(10_000, 5_000, slot))
+            .collect();
+        assert!(matches!(
+            start_timer(&mut timers, &config),
+            StartOutcome::Dropped
+        ));
+        assert_eq!(slots(&timers), [0, 1, 2]);
+    }
+
+    #[test]
+    fn overwriting_replaces_the_oldest_timer() {
+        let config = slot_config(true);
+        let mut timers = vec![
+            Timer::with_remaining(10_000, 6_000, 0),
+            Timer::with_remaining(10_000, 2_000, 1),
+            Timer::with_remaining(10_000, 8_000, 2),
+        ];
+        match start_timer(&mut timers, &config) {
+            StartOutcome::Overwrote { replaced, number } => {
+                assert_eq!(replaced.slot, 1);
+                assert_eq!(number, 2);
+            }
+            outcome => panic!("expected an overwrite, got {outcome:?}"),
+        }
+        assert_eq!(slots(&timers), [0, 2, 1]);
+    }
+
+    #[test]
+    fn lingering_timers_make_way_first() {
+        let config = slot_config(true);
+        let mut timers = vec![
+            Timer::with_remaining(10_000, 2_000, 0),
+            Timer::with_remaining(10_000, -300, 1),
+            Timer::with_remaining(10_000, -100, 2),
+        ];
+        assert!(matches!(
+            start_timer(&mut timers, &config),
+            StartOutcome::Started
+        ));
+        assert_eq!(slots(&timers), [0, 2, 1]);
+    }
+
+    #[test]
+    fn new_timers_on_the_left() {
+        let config = Config {
+            add_new_on_left: true,
+            ..slot_config(false)
+        };
+        let mut timers = Vec::new();
+        start_timer(&mut timers, &config);
+        start_timer(&mut timers, &config);
+        assert_eq!(slots(&timers), [1, 0]);
+    }
+
+    fn fill(template: &str) -> String {
+        fill_template(template, |key| match key {
+            "label" => Some("Smoke".to_string()),
+            "n" => Some("2".to_string()),
+            "empty" => Some(String::new()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn placeholders_are_filled() {
+        assert_eq!(fill("{label} {n}"), "Smoke 2");
+        assert_eq!(fill("#{n}: {label}!"), "#2: Smoke!");
+        assert_eq!(fill("{empty}{n}"), "2");
+        assert_eq!(fill("no placeholders"), "no placeholders");
+    }
+
+    #[test]
+    fn unknown_and_unclosed_placeholders_are_kept() {
+        assert_eq!(fill("{unknown} {n}"), "{unknown} 2");
+        assert_eq!(fill("{n"), "{n");
+        assert_eq!(fill("{{n}}"), "{2}");
+        assert_eq!(fill("}{"), "}{");
+    }
+
+    #[test]
+    fn empty_placeholders_leave_no_gaps() {
+        let config = Config {
+            subtext_template: "{label} {n} ({profile})".to_string(),
+            subtext_string: "Smoke".to_string(),
+            show_subtext: true,
+            show_numbering: false,
+            ..Config::default()
+        };
+        let renderer = OverlayRenderer {
+            config: &config,
+            profile: Some("viper"),
+            icon: None,
+        };
+        assert_eq!(renderer.subtext(2, 0, Local::now(), 1), "Smoke (viper)");
+    }
+}
@@ -0,0 +1,339 @@
+use crate::config::{Config, SessionLogFormat};
+use crate::overlay::Timer;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, NaiveDateTime, SecondsFormat, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const LOG_DIR_NAME: &str = "logs";
+const LOG_PREFIX: &str = "session-";
+const LOG_TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+const CSV_HEADER: &str = "time,event,timer,started,duration,remaining";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEvent {
+    /// A timer started
+    Activation,
+    /// The activation sequence was canceled between the start and confirm keys
+    Cancellation,
+    /// A running timer was replaced by `overwrite_oldest` to make room for a new one
+    Eviction,
+    /// An activation was dropped because every slot was taken
+    Dropped,
+    Expiry,
+}
+
+impl SessionEvent {
+    const ALL: [SessionEvent; 5] = [
+        SessionEvent::Activation,
+        SessionEvent::Cancellation,
+        SessionEvent::Eviction,
+        SessionEvent::Dropped,
+        SessionEvent::Expiry,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            SessionEvent::Activation => "activation",
+            SessionEvent::Cancellation => "cancellation",
+            SessionEvent::Eviction => "eviction",
+            SessionEvent::Dropped => "dropped",
+            SessionEvent::Expiry => "expiry",
+        }
+    }
+}
+
+/// A line of the session log. Events that happened to a timer say which one it was
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Wall-clock time of the event, in RFC 3339
+    pub time: String,
+    pub event: SessionEvent,
+    /// The number shown with the timer
+    pub timer: Option<usize>,
+    /// When the timer started, which ties its events together
+    pub started: Option<String>,
+    /// Seconds the timer started with
+    pub duration: Option<f32>,
+    /// Seconds the timer had left
+    pub remaining: Option<f32>,
+}
+
+impl LogRecord {
+    fn new(event: SessionEvent, timer: Option<(&Timer, usize)>) -> Self {
+        Self {
+            time: format_time(Local::now()),
+            event,
+            timer: timer.map(|(_, number)| number),
+            started: timer.map(|(timer, _)| format_time(timer.started_at)),
+            duration: timer.map(|(timer, _)| timer.duration_ms as f32 / 1000.0),
+            remaining: timer.map(|(timer, _)| timer.remaining_ms() as f32 / 1000.0),
+        }
+    }
+
+    /// Parse a line written by `to_csv`, which also skips the header
+    fn from_csv(line: &str) -> Option<Self> {
+        fn field<T: std::str::FromStr>(value: &str) -> Option<Option<T>> {
+            if value.is_empty() {
+                Some(None)
+            } else {
+                value.parse().ok().map(Some)
+            }
+        }
+
+        let fields: Vec<&str> = line.split(',').collect();
+        let [time, event, timer, started, duration, remaining] = fields[..] else {
+            return None;
+        };
+
+        Some(Self {
+            time: time.to_string(),
+            event: SessionEvent::ALL.into_iter().find(|e| e.key() == event)?,
+            timer: field(timer)?,
+            started: field(started)?,
+            duration: field(duration)?,
+            remaining: field(remaining)?,
+        })
+    }
+
+    fn to_csv(&self) -> String {
+        fn field<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(T::to_string).unwrap_or_default()
+        }
+
+        format!(
+            "{},{},{},{},{},{}",
+            self.time,
+            self.event.key(),
+            field(&self.timer),
+            field(&self.started),
+            field(&self.duration),
+            field(&self.remaining),
+        )
+    }
+}
+
+fn format_time(time: DateTime<Local>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, false)
+}
+
+/// Parse a time written by `format_time`
+pub fn parse_time(time: &str) -> Option<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|time| time.with_timezone(&Local))
+}
+
+/// A session log file
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    pub path: PathBuf,
+    pub started: DateTime<Local>,
+}
+
+/// List the session logs, newest first
+pub fn list() -> Result<Vec<LogFile>> {
+    list_in(&log_dir()?)
+}
+
+fn list_in(dir: &Path) -> Result<Vec<LogFile>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let entries =
+        fs::read_dir(dir).with_context(|| format!("Failed to read log directory: {dir:?}"))?;
+
+    let mut logs: Vec<LogFile> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let timestamp = path.file_stem()?.to_str()?.strip_prefix(LOG_PREFIX)?;
+            let naive = NaiveDateTime::parse_from_str(timestamp, LOG_TIMESTAMP_FORMAT).ok()?;
+            let started = Local.from_local_datetime(&naive).earliest()?;
+            Some(LogFile { path, started })
+        })
+        .collect();
+
+    logs.sort_by_key(|log| Reverse(log.started));
+
+    Ok(logs)
+}
+
+/// Read the records of a session log in either format. Lines that can't be parsed, such
+/// as one cut off when the overlay was closed, are skipped
+pub fn read(path: &Path) -> Result<Vec<LogRecord>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read session log: {path:?}"))?;
+    let is_csv = path.extension().is_some_and(|extension| extension == "csv");
+
+    Ok(contents
+        .lines()
+        .filter_map(|line| {
+            if is_csv {
+                LogRecord::from_csv(line)
+            } else {
+                serde_json::from_str(line).ok()
+            }
+        })
+        .collect())
+}
+
+/// Get the directory that session logs are kept in
+pub fn log_dir() -> Result<PathBuf> {
+    Ok(Config::get_config_dir()?.join(LOG_DIR_NAME))
+}
+
+/// A log file for one run of the overlay that events are appended to as they happen
+pub struct SessionLog {
+    file: File,
+    format: SessionLogFormat,
+}
+
+impl SessionLog {
+    /// Start a new log file in the log directory, named after the time the session started
+    pub fn create(format: SessionLogFormat) -> Result<Self> {
+        let extension = match format {
+            SessionLogFormat::Off => bail!("Session log is turned off"),
+            SessionLogFormat::Jsonl => "jsonl",
+            SessionLogFormat::Csv => "csv",
+        };
+
+        let dir = log_dir()?;
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create log directory: {dir:?}"))?;
+
+        let name = format!(
+            "{LOG_PREFIX}{}.{extension}",
+            Local::now().format(LOG_TIMESTAMP_FORMAT)
+        );
+        let path = dir.join(name);
+        let mut file = File::create(&path)
+            .with_context(|| format!("Failed to create session log: {path:?}"))?;
+
+        if format == SessionLogFormat::Csv {
+            writeln!(file, "{CSV_HEADER}")
+                .with_context(|| format!("Failed to write session log: {path:?}"))?;
+        }
+
+        Ok(Self { file, format })
+    }
+
+    /// Append an event, along with the timer and the number it shows if it happened to one
+    pub fn write(&mut self, event: SessionEvent, timer: Option<(&Timer, usize)>) {
+        let record = LogRecord::new(event, timer);
+        let line = match self.format {
+            SessionLogFormat::Jsonl => match serde_json::to_string(&record) {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("Failed to serialize session log record: {e:?}");
+                    return;
+                }
+            },
+            SessionLogFormat::Csv => record.to_csv(),
+            SessionLogFormat::Off => return,
+        };
+
+        if let Err(e) = writeln!(self.file, "{line}") {
+            eprintln!("Failed to write session log: {e:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Make a fresh directory named after the test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "{}-log-{name}-{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn records() -> Vec<LogRecord> {
+        vec![
+            LogRecord {
+                time: "2025-03-01T20:15:00.250+01:00".to_string(),
+                event: SessionEvent::Activation,
+                timer: Some(2),
+                started: Some("2025-03-01T20:15:00.250+01:00".to_string()),
+                duration: Some(19.5),
+                remaining: Some(19.5),
+            },
+            LogRecord {
+                time: "2025-03-01T20:15:03.000+01:00".to_string(),
+                event: SessionEvent::Cancellation,
+                timer: None,
+                started: None,
+                duration: None,
+                remaining: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn csv_round_trips() {
+        for record in records() {
+            assert_eq!(LogRecord::from_csv(&record.to_csv()), Some(record));
+        }
+        assert_eq!(LogRecord::from_csv(CSV_HEADER), None);
+        assert_eq!(
+            LogRecord::from_csv("2025-03-01T20:15:00.250+01:00,act"),
+            None
+        );
+    }
+
+    #[test]
+    fn jsonl_is_read_back() {
+        let dir = temp_dir("jsonl");
+        let path = dir.join("session-20250301-201500.jsonl");
+        let mut contents: String = records()
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap() + "\n")
+            .collect();
+        // A line cut off when the overlay was closed
+        contents.push_str(r#"{"time":"2025-03-01T20:15:04"#);
+        fs::write(&path, contents).unwrap();
+
+        assert_eq!(read(&path).unwrap(), records());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn logs_are_listed_newest_first() {
+        let dir = temp_dir("list");
+        for name in [
+            "session-20250301-201500.csv",
+            "session-20250302-090000.jsonl",
+            "session-20250228-235959.jsonl",
+            "notes.txt",
+            "session-yesterday.csv",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let names: Vec<String> = list_in(&dir)
+            .unwrap()
+            .iter()
+            .map(|log| log.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "session-20250302-090000.jsonl",
+                "session-20250301-201500.csv",
+                "session-20250228-235959.jsonl",
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+use crate::session_log::{self, LogFile, LogRecord, SessionEvent, parse_time};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+
+/// A timer's run in a session, in seconds since the session's first event
+#[derive(Debug, Clone)]
+pub struct TimelineBar {
+    pub start: f32,
+    pub end: f32,
+    /// Row of the timeline the bar is drawn in, so overlapping timers don't cover each other
+    pub lane: usize,
+    /// Whether the timer was overwritten before it ran out
+    pub evicted: bool,
+}
+
+/// What happened in one session, built from its log
+#[derive(Debug, Clone)]
+pub struct SessionStats {
+    pub started: DateTime<Local>,
+    pub activations: usize,
+    pub cancellations: usize,
+    pub evictions: usize,
+    pub dropped: usize,
+    /// Average seconds between consecutive activations
+    pub average_gap: Option<f32>,
+    pub timeline: Vec<TimelineBar>,
+    /// Seconds since the session's first event that activations were dropped at
+    pub dropped_at: Vec<f32>,
+    /// Seconds from the session's first event until its last one, or its last timer ending
+    pub length: f32,
+}
+
+impl SessionStats {
+    fn new(log: &LogFile, records: &[LogRecord]) -> Self {
+        let count = |event| records.iter().filter(|r| r.event == event).count();
+        let origin = records
+            .first()
+            .and_then(|r| parse_time(&r.time))
+            .unwrap_or(log.started);
+        let offset = |time: &str| {
+            parse_time(time).map(|time| (time - origin).num_milliseconds() as f32 / 1000.0)
+        };
+
+        let activations: Vec<&LogRecord> = records
+            .iter()
+            .filter(|r| r.event == SessionEvent::Activation)
+            .collect();
+        let activation_times: Vec<f32> =
+            activations.iter().filter_map(|r| offset(&r.time)).collect();
+        let average_gap = match activation_times[..] {
+            [first, .., last] => Some((last - first) / (activation_times.len() - 1) as f32),
+            _ => None,
+        };
+
+        let mut lane_ends: Vec<f32> = Vec::new();
+        let timeline: Vec<TimelineBar> = activations
+            .iter()
+            .filter_map(|activation| {
+                let start = offset(&activation.time)?;
+                // Overwriting a timer cuts it short
+                let eviction = records
+                    .iter()
+                    .find(|r| r.event == SessionEvent::Eviction && r.started == activation.started);
+                let end = match eviction {
+                    Some(eviction) => offset(&eviction.time)?,
+                    None => start + activation.duration?,
+                };
+
+                // Use the first row that's free by the time this timer starts
+                let lane = match lane_ends.iter().position(|&lane_end| lane_end <= start) {
+                    Some(lane) => lane,
+                    None => {
+                        lane_ends.push(0.0);
+                        lane_ends.len() - 1
+                    }
+                };
+                lane_ends[lane] = end;
+
+                Some(TimelineBar {
+                    start,
+                    end,
+                    lane,
+                    evicted: eviction.is_some(),
+                })
+            })
+            .collect();
+
+        let dropped_at: Vec<f32> = records
+            .iter()
+            .filter(|r| r.event == SessionEvent::Dropped)
+            .filter_map(|r| offset(&r.time))
+            .collect();
+
+        let length = records
+            .iter()
+            .filter_map(|r| offset(&r.time))
+            .chain(timeline.iter().map(|bar| bar.end))
+            .fold(0.0, f32::max);
+
+        Self {
+            started: log.started,
+            activations: activations.len(),
+            cancellations: count(SessionEvent::Cancellation),
+            evictions: count(SessionEvent::Eviction),
+            dropped: count(SessionEvent::Dropped),
+            average_gap,
+            timeline,
+            dropped_at,
+            length,
+        }
+    }
+
+    /// How many activations found every slot taken, and were dropped or overwrote a timer
+    pub fn full(&self) -> usize {
+        self.evictions + self.dropped
+    }
+
+    /// Share of activations that found every slot taken, from 0.0 to 1.0
+    pub fn full_ratio(&self) -> Option<f32> {
+        let attempts = self.activations + self.dropped;
+        (attempts > 0).then(|| self.full() as f32 / attempts as f32)
+    }
+
+    /// Number of timeline rows, the most timers that were running at once
+    pub fn lanes(&self) -> usize {
+        self.timeline
+            .iter()
+            .map(|bar| bar.lane + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Build the stats of every logged session that has any events, newest first
+pub fn load() -> Result<Vec<SessionStats>> {
+    Ok(session_log::list()?
+        .iter()
+        .filter_map(|log| match session_log::read(&log.path) {
+            Ok(records) if records.is_empty() => None,
+            Ok(records) => Some(SessionStats::new(log, &records)),
+            Err(e) => {
+                eprintln!("{e:?}");
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{SecondsFormat, TimeDelta};
+    use std::path::PathBuf;
+
+    const ORIGIN: &str = "2025-03-01T20:00:00.000+00:00";
+
+    /// Get the log time `secs` after the session started
+    fn at(secs: f32) -> String {
+        let origin = DateTime::parse_from_rfc3339(ORIGIN).unwrap();
+        (origin + TimeDelta::milliseconds((secs * 1000.0) as i64))
+            .to_rfc3339_opts(SecondsFormat::Millis, false)
+    }
+
+    fn record(secs: f32, event: SessionEvent) -> LogRecord {
+        LogRecord {
+            time: at(secs),
+            event,
+            timer: None,
+            started: None,
+            duration: None,
+            remaining: None,
+        }
+    }
+
+    fn activation(secs: f32) -> LogRecord {
+        LogRecord {
+            started: Some(at(secs)),
+            duration: Some(20.0),
+            ..record(secs, SessionEvent::Activation)
+        }
+    }
+
+    fn eviction(secs: f32, started: f32) -> LogRecord {
+        LogRecord {
+            started: Some(at(started)),
+            ..record(secs, SessionEvent::Eviction)
+        }
+    }
+
+    fn summarize(records: &[LogRecord]) -> SessionStats {
+        let log = LogFile {
+            path: PathBuf::from("session-20250301-200000.jsonl"),
+            started: parse_time(ORIGIN).unwrap(),
+        };
+        SessionStats::new(&log, records)
+    }
+
+    #[test]
+    fn session_is_summarized() {
+        let stats = summarize(&[
+            activation(0.0),
+            activation(5.0),
+            record(8.0, SessionEvent::Cancellation),
+            activation(10.0),
+            eviction(12.0, 0.0),
+            activation(15.0),
+            record(16.0, SessionEvent::Dropped),
+        ]);
+
+        assert_eq!(stats.activations, 4);
+        assert_eq!(stats.cancellations, 1);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.dropped, 1);
+        assert_eq!(stats.full(), 2);
+        assert_eq!(stats.full_ratio(), Some(0.4));
+        assert_eq!(stats.average_gap, Some(5.0));
+        assert_eq!(stats.dropped_at, [16.0]);
+        assert_eq!(stats.length, 35.0);
+    }
+
+    #[test]
+    fn timers_share_lanes_once_free() {
+        let stats = summarize(&[
+            activation(0.0),
+            activation(5.0),
+            activation(10.0),
+            eviction(12.0, 0.0),
+            activation(15.0),
+        ]);
+
+        let bars: Vec<(f32, f32, usize, bool)> = stats
+            .timeline
+            .iter()
+            .map(|bar| (bar.start, bar.end, bar.lane, bar.evicted))
+            .collect();
+        // The evicted timer ends early, so its lane is free again for the last one
+        assert_eq!(
+            bars,
+            [
+                (0.0, 12.0, 0, true),
+                (5.0, 25.0, 1, false),
+                (10.0, 30.0, 2, false),
+                (15.0, 35.0, 0, false),
+            ]
+        );
+        assert_eq!(stats.lanes(), 3);
+    }
+
+    #[test]
+    fn quiet_sessions_have_no_rates() {
+        let stats = summarize(&[record(0.0, SessionEvent::Cancellation)]);
+        assert_eq!(stats.average_gap, None);
+        assert_eq!(stats.full_ratio(), None);
+        assert_eq!(stats.lanes(), 0);
+
+        let stats = summarize(&[activation(3.0)]);
+        assert_eq!(stats.average_gap, None);
+        assert_eq!(stats.full_ratio(), Some(0.0));
+    }
+}